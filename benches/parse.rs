@@ -0,0 +1,73 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use tx_engine::output::AtomicFile;
+use tx_engine::{byte_record_iter, path_reader, record_iter};
+
+/// Number of rows in the generated input, override with `TX_ENGINE_BENCH_ROWS`
+const DEFAULT_ROWS: u64 = 10_000_000;
+
+/// Generate an input file with a mix of all transaction types, reusing it across runs
+fn generate_input(rows: u64) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tx_engine_bench_{rows}.csv"));
+    if Path::new(&path).exists() {
+        return path;
+    }
+
+    // written to a temporary file first, so that an interrupted run leaves no truncated input behind
+    let mut writer = AtomicFile::create(&path).expect("Failed to create bench input");
+    writeln!(writer, "type, client, tx, amount").unwrap();
+    for tx in 0..rows {
+        let client = tx % u16::MAX as u64;
+        match tx % 10 {
+            0..=4 => writeln!(
+                writer,
+                "deposit, {client}, {tx}, {}.{:04}",
+                tx % 1000,
+                tx % 10000
+            ),
+            5..=7 => writeln!(writer, "withdrawal, {client}, {tx}, {}.5", tx % 100),
+            8 => writeln!(writer, "dispute, {client}, {}, ", tx - 8),
+            _ => writeln!(writer, "resolve, {client}, {}, ", tx - 9),
+        }
+        .unwrap();
+    }
+    writer.commit().expect("Failed to write bench input");
+    path
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let rows = std::env::var("TX_ENGINE_BENCH_ROWS")
+        .ok()
+        .and_then(|rows| rows.parse().ok())
+        .unwrap_or(DEFAULT_ROWS);
+    let path = generate_input(rows);
+    let path = path.to_str().unwrap();
+
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(rows));
+
+    group.bench_function("record_iter", |b| {
+        b.iter(|| {
+            let mut reader = path_reader(path).unwrap();
+            record_iter(&mut reader).filter(Result::is_ok).count()
+        })
+    });
+
+    group.bench_function("byte_record_iter", |b| {
+        b.iter(|| {
+            let mut reader = path_reader(path).unwrap();
+            byte_record_iter(&mut reader)
+                .unwrap()
+                .filter(Result::is_ok)
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,186 @@
+//! Engine settings.
+
+use std::str::FromStr;
+
+use encoding_rs::Encoding;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+use crate::control::ControlConfig;
+use crate::output::OutputFormat;
+use crate::parse::AmountFormat;
+use crate::types::AccountRow;
+
+/// Character encoding of the input.
+/// A byte order mark takes precedence over the configured encoding.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum InputEncoding {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, decoded as its Windows-1252 superset like browsers do
+    Latin1,
+    Windows1252,
+}
+
+impl InputEncoding {
+    /// Encoding to transcode from, `None` if the input is already UTF-8
+    pub fn encoding(self) -> Option<&'static Encoding> {
+        match self {
+            InputEncoding::Utf8 => None,
+            InputEncoding::Latin1 | InputEncoding::Windows1252 => Some(encoding_rs::WINDOWS_1252),
+        }
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(InputEncoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(InputEncoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(InputEncoding::Windows1252),
+            _ => Err(anyhow::anyhow!("Unsupported encoding: {s}")),
+        }
+    }
+}
+
+/// Settings for reading the input
+#[derive(Debug, Default, Clone)]
+pub struct InputConfig {
+    /// Character encoding of the input
+    pub encoding: InputEncoding,
+    /// Format of the `amount` column
+    pub amount: AmountFormat,
+}
+
+/// Dispute and locking rules applied to every transaction
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Lock the account of a client on a chargeback
+    pub lock_on_chargeback: bool,
+    /// Ignore disputes of deposits larger than the available funds, instead of letting them go negative
+    pub dispute_requires_funds: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            lock_on_chargeback: true,
+            dispute_requires_funds: false,
+        }
+    }
+}
+
+/// Settings for processing a batch of transactions
+#[derive(Debug, Default, Clone)]
+pub struct ProcessConfig {
+    /// Input dialect
+    pub input: InputConfig,
+    /// Control total check against a trailer row or a sidecar file
+    pub control: ControlConfig,
+    /// Dispute and locking rules
+    pub rules: Rules,
+    /// Fail on the first malformed row or ignored transaction, leaving the accounts unchanged
+    pub strict: bool,
+}
+
+/// Order of accounts in the output
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AccountOrder {
+    /// Ascending client ID
+    #[default]
+    ClientId,
+    /// Descending total, then ascending client ID
+    Total,
+    /// Locked accounts first, then ascending client ID
+    LockedFirst,
+}
+
+impl AccountOrder {
+    /// Sort output rows in this order
+    pub fn sort(self, rows: &mut [AccountRow]) {
+        match self {
+            AccountOrder::ClientId => rows.sort_by_key(|row| row.client),
+            AccountOrder::Total => {
+                rows.sort_by(|a, b| b.total.cmp(&a.total).then(a.client.cmp(&b.client)))
+            }
+            AccountOrder::LockedFirst => rows.sort_by_key(|row| (!row.locked, row.client)),
+        }
+    }
+}
+
+impl FromStr for AccountOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(AccountOrder::ClientId),
+            "total" => Ok(AccountOrder::Total),
+            "locked" => Ok(AccountOrder::LockedFirst),
+            _ => Err(anyhow::anyhow!("Unknown account order: {s}")),
+        }
+    }
+}
+
+/// Rounding of output amounts to the output precision
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// Midpoints round away from zero, `-0.00005` becomes `-0.0001`
+    HalfUp,
+    /// Midpoints round to the nearest even digit, also known as banker's rounding
+    #[default]
+    HalfEven,
+    /// Extra digits are dropped, rounding towards zero
+    Truncate,
+}
+
+impl Rounding {
+    /// Round an amount to the given number of decimal places
+    pub fn round(self, amount: Decimal, dp: u32) -> Decimal {
+        let strategy = match self {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::Truncate => RoundingStrategy::ToZero,
+        };
+        amount.round_dp_with_strategy(dp, strategy)
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            "truncate" => Ok(Rounding::Truncate),
+            _ => Err(anyhow::anyhow!("Unknown rounding: {s}")),
+        }
+    }
+}
+
+/// Settings for writing accounts
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    /// Format of the output
+    pub format: OutputFormat,
+    /// Order of the account rows
+    pub order: AccountOrder,
+    /// Decimal places of output amounts
+    pub precision: u32,
+    /// Rounding of output amounts to the precision
+    pub rounding: Rounding,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::default(),
+            order: AccountOrder::default(),
+            precision: 4,
+            rounding: Rounding::default(),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Control totals declared by a batch file, checked against the rows actually read.
+//!
+//! Totals are declared either by a trailer row at the end of the input:
+//! ```csv
+//! type, client, tx, amount
+//! deposit, 1, 1, 10.0
+//! withdrawal, 1, 2, 5.0
+//! trailer, , 2, 15.0
+//! ```
+//! where `tx` holds the number of records and `amount` holds the sum of all amounts,
+//! or by a sidecar CSV file with `records` and `amount` columns.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use csv::ReaderBuilder;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// Record count and sum of amounts of a batch
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct ControlTotal {
+    pub records: u64,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub amount: Decimal,
+}
+
+impl ControlTotal {
+    /// Read control totals from a sidecar CSV file
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .context(format!("Failed to open {path}"))?;
+        reader
+            .deserialize()
+            .next()
+            .context(format!("Missing control totals in {path}"))?
+            .context(format!("Failed to deserialize control totals in {path}"))
+    }
+}
+
+/// Where the control totals of a batch are declared
+#[derive(Debug, Default, Clone)]
+pub enum ControlSource {
+    /// Control totals are not checked
+    #[default]
+    None,
+    /// Trailer row at the end of the input
+    Trailer,
+    /// Sidecar file next to the input
+    Sidecar(PathBuf),
+}
+
+/// What to do when control totals do not match
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MismatchAction {
+    /// Fail processing
+    #[default]
+    Fail,
+    /// Carry on, returning the mismatch as a warning
+    Warn,
+}
+
+/// Control total check settings
+#[derive(Debug, Default, Clone)]
+pub struct ControlConfig {
+    pub source: ControlSource,
+    pub on_mismatch: MismatchAction,
+}
+
+impl ControlConfig {
+    /// Compare the rows actually read against the declared control totals.
+    /// `trailer` is the trailer row found in the input, if any.
+    /// Returns the mismatch as a warning if mismatches only warn.
+    pub fn check(
+        &self,
+        read: &ControlTotal,
+        trailer: Option<&ControlTotal>,
+    ) -> anyhow::Result<Option<String>> {
+        let declared = match &self.source {
+            ControlSource::None => return Ok(None),
+            ControlSource::Trailer => trailer.cloned(),
+            ControlSource::Sidecar(path) => {
+                let path = path.to_string_lossy();
+                Some(ControlTotal::from_path(&path)?)
+            }
+        };
+
+        let error = match declared {
+            Some(declared) if declared == *read => return Ok(None),
+            Some(declared) => anyhow::anyhow!(
+                "Control total mismatch: declared {} records totalling {}, read {} records totalling {}",
+                declared.records,
+                declared.amount,
+                read.records,
+                read.amount
+            ),
+            None => anyhow::anyhow!("Missing trailer row with control totals"),
+        };
+
+        match self.on_mismatch {
+            MismatchAction::Fail => Err(error),
+            MismatchAction::Warn => Ok(Some(error.to_string())),
+        }
+    }
+}
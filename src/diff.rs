@@ -0,0 +1,162 @@
+//! Differences between two account snapshots.
+//!
+//! Snapshots are account outputs of earlier runs, in any of the [OutputFormat](crate::output::OutputFormat)s.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use csv::ReaderBuilder;
+use rust_decimal::Decimal;
+
+use crate::types::AccountRow;
+
+/// Accounts of a snapshot by client
+pub type Snapshot = BTreeMap<u16, AccountRow>;
+
+/// Read an account snapshot from a file
+pub fn read_snapshot(path: &Path) -> anyhow::Result<Snapshot> {
+    let file = std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    read_accounts(file).context(format!("Failed to read accounts from {}", path.display()))
+}
+
+/// Read account rows written as CSV, a JSON array or one JSON object per line
+pub fn read_accounts<R: Read>(mut reader: R) -> anyhow::Result<Snapshot> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+
+    let rows: Vec<AccountRow> = match input.trim_start().chars().next() {
+        Some('[') => serde_json::from_str(&input)?,
+        Some('{') => serde_json::Deserializer::from_str(&input)
+            .into_iter()
+            .collect::<Result<_, _>>()?,
+        _ => ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(input.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut snapshot = Snapshot::new();
+    for row in rows {
+        let client = row.client;
+        if snapshot.insert(client, row).is_some() {
+            anyhow::bail!("Duplicate client {client}");
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Change of a client account between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountChange {
+    pub before: AccountRow,
+    pub after: AccountRow,
+}
+
+impl AccountChange {
+    pub fn client(&self) -> u16 {
+        self.after.client
+    }
+
+    /// Change of the available funds, saturating like the balances of the engine
+    pub fn available(&self) -> Decimal {
+        self.after.available.saturating_sub(self.before.available)
+    }
+
+    pub fn held(&self) -> Decimal {
+        self.after.held.saturating_sub(self.before.held)
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.after.total.saturating_sub(self.before.total)
+    }
+
+    /// Whether the account was locked or unlocked
+    pub fn locked(&self) -> bool {
+        self.after.locked != self.before.locked
+    }
+}
+
+/// Differences between two snapshots, each in ascending client order
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AccountDiff {
+    pub added: Vec<AccountRow>,
+    pub removed: Vec<AccountRow>,
+    pub changed: Vec<AccountChange>,
+}
+
+impl AccountDiff {
+    /// Whether the snapshots are identical
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Write the differences as human readable text, one client per line
+    pub fn write_text<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        for row in &self.added {
+            writeln!(writer, "+ client {}: {}", row.client, account(row))?;
+        }
+        for row in &self.removed {
+            writeln!(writer, "- client {}: {}", row.client, account(row))?;
+        }
+        for change in &self.changed {
+            let mut fields = Vec::new();
+            for (name, delta) in [
+                ("available", change.available()),
+                ("held", change.held()),
+                ("total", change.total()),
+            ] {
+                if !delta.is_zero() {
+                    fields.push(format!("{name} {delta:+}"));
+                }
+            }
+            if change.locked() {
+                fields.push(format!(
+                    "locked {} -> {}",
+                    change.before.locked, change.after.locked
+                ));
+            }
+            writeln!(
+                writer,
+                "~ client {}: {}",
+                change.client(),
+                fields.join(", ")
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Compare two account snapshots
+pub fn diff_accounts(before: &Snapshot, after: &Snapshot) -> AccountDiff {
+    let mut diff = AccountDiff::default();
+    for (client, row) in before {
+        match after.get(client) {
+            None => diff.removed.push(row.clone()),
+            // amounts compare by value, so that `1.5` and `1.50` are equal
+            Some(other) if other != row => diff.changed.push(AccountChange {
+                before: row.clone(),
+                after: other.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    diff.added = after
+        .iter()
+        .filter(|(client, _)| !before.contains_key(client))
+        .map(|(_, row)| row.clone())
+        .collect();
+    diff
+}
+
+fn account(row: &AccountRow) -> String {
+    let locked = if row.locked { ", locked" } else { "" };
+    format!(
+        "available {}, held {}, total {}{locked}",
+        row.available, row.held, row.total
+    )
+}
@@ -0,0 +1,158 @@
+//! Report of currently open disputes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::types::{Accounts, DisputeState, TxOutcome, TxType};
+use crate::{TxEvent, TxObserver};
+
+/// Records where in the input each open dispute was raised
+#[derive(Debug, Default)]
+pub struct DisputeTracker {
+    /// Line of the dispute by client and deposit tx
+    opened: HashMap<(u16, u32), u64>,
+}
+
+impl DisputeTracker {
+    /// Line where the open dispute of a deposit was raised
+    pub fn opened_at(&self, client: u16, tx: u32) -> Option<u64> {
+        self.opened.get(&(client, tx)).copied()
+    }
+}
+
+impl TxObserver for DisputeTracker {
+    fn on_tx(&mut self, event: &TxEvent<'_>, _accounts: &Accounts) -> anyhow::Result<()> {
+        if event.outcome != TxOutcome::Applied {
+            return Ok(());
+        }
+
+        match event.tx {
+            TxType::Dispute(dispute) => {
+                self.opened.insert((dispute.client, dispute.tx), event.line);
+            }
+            TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                self.opened.remove(&(dispute.client, dispute.tx));
+            }
+            TxType::Deposit(_) | TxType::Withdrawal(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Currently disputed deposit
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenDispute {
+    pub client: u16,
+    pub tx: u32,
+    /// Amount held for the dispute
+    pub amount: Decimal,
+    /// Line in the input where the dispute was raised
+    pub line: Option<u64>,
+}
+
+/// Sum of open disputes of a client
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientDisputes {
+    pub client: u16,
+    pub disputes: usize,
+    /// Sum of the disputed amounts, which equals the held balance of the client
+    /// unless its account was opened with held funds
+    pub amount: Decimal,
+    /// Held balance of the client account
+    pub held: Decimal,
+}
+
+impl ClientDisputes {
+    /// Held funds without an open dispute behind them, such as opening balances
+    pub fn undisputed(&self) -> Decimal {
+        self.held.saturating_sub(self.amount)
+    }
+}
+
+/// Open disputes, largest amount first, with per-client totals
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisputeReport {
+    pub disputes: Vec<OpenDispute>,
+    /// Totals in ascending client order
+    pub clients: Vec<ClientDisputes>,
+}
+
+impl DisputeReport {
+    /// Build the report from the deposit history of all accounts
+    pub fn new(accounts: &Accounts, tracker: &DisputeTracker) -> Self {
+        let mut disputes = Vec::new();
+        let mut clients = BTreeMap::new();
+        for (client, account) in accounts.iter() {
+            for (tx, deposit) in &account.deposit_txs {
+                if deposit.dispute != DisputeState::Disputed {
+                    continue;
+                }
+
+                disputes.push(OpenDispute {
+                    client: *client,
+                    tx: *tx,
+                    amount: deposit.amount,
+                    line: tracker.opened_at(*client, *tx),
+                });
+                let totals = clients.entry(*client).or_insert(ClientDisputes {
+                    client: *client,
+                    disputes: 0,
+                    amount: Decimal::ZERO,
+                    held: account.held,
+                });
+                totals.disputes += 1;
+                totals.amount = totals.amount.saturating_add(deposit.amount);
+            }
+        }
+
+        disputes.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then(a.client.cmp(&b.client))
+                .then(a.tx.cmp(&b.tx))
+        });
+        Self {
+            disputes,
+            clients: clients.into_values().collect(),
+        }
+    }
+
+    /// Write the open disputes as CSV, each with the total disputed amount of its client
+    pub fn write_csv<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+        #[derive(Serialize)]
+        struct Row {
+            client: u16,
+            tx: u32,
+            #[serde(with = "rust_decimal::serde::str")]
+            amount: Decimal,
+            line: Option<u64>,
+            #[serde(with = "rust_decimal::serde::str")]
+            client_total: Decimal,
+            #[serde(with = "rust_decimal::serde::str")]
+            client_held: Decimal,
+        }
+
+        let totals: HashMap<u16, &ClientDisputes> = self
+            .clients
+            .iter()
+            .map(|client| (client.client, client))
+            .collect();
+        let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+        for dispute in &self.disputes {
+            writer.serialize(Row {
+                client: dispute.client,
+                tx: dispute.tx,
+                amount: dispute.amount.round_dp(4),
+                line: dispute.line,
+                client_total: totals[&dispute.client].amount.round_dp(4),
+                client_held: totals[&dispute.client].held.round_dp(4),
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
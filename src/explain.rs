@@ -0,0 +1,76 @@
+//! Explanation of how a client reached its final state.
+//!
+//! Every transaction of the client is traced as it is processed by [crate::process_tx],
+//! so the explanation always agrees with the real result.
+
+use std::io::Write;
+
+use crate::types::{Accounts, Balances};
+use crate::{TxEvent, TxObserver};
+
+/// Writes every transaction of a client with its outcome and the balances before and after it
+pub struct Explainer<W: Write> {
+    writer: W,
+    client: u16,
+    /// Balances of the client after the last transaction
+    balances: Balances,
+    txs: u64,
+}
+
+impl<W: Write> Explainer<W> {
+    /// Explain a client from the current state of the accounts
+    pub fn new(writer: W, client: u16, accounts: &Accounts) -> Self {
+        Self {
+            writer,
+            client,
+            balances: Balances::of(accounts, client),
+            txs: 0,
+        }
+    }
+
+    /// Write the final state of the client and return the underlying writer
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        match self.txs {
+            0 => writeln!(self.writer, "No transactions of client {}", self.client)?,
+            txs => writeln!(
+                self.writer,
+                "Client {} after {txs} transactions: {}",
+                self.client, self.balances
+            )?,
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> TxObserver for Explainer<W> {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        if event.tx.client() != self.client {
+            return Ok(());
+        }
+
+        self.txs += 1;
+        let amount = event
+            .amount(accounts)
+            .map(|amount| format!(" of {}", amount.round_dp(4)))
+            .unwrap_or_default();
+        writeln!(
+            self.writer,
+            "line {}: {} tx {}{amount}, {}",
+            event.line,
+            event.tx.name(),
+            event.tx.tx(),
+            event.outcome
+        )?;
+
+        let after = Balances::of(accounts, self.client);
+        if after == self.balances {
+            writeln!(self.writer, "  unchanged: {after}")?;
+        } else {
+            writeln!(self.writer, "  before:    {}", self.balances)?;
+            writeln!(self.writer, "  after:     {after}")?;
+        }
+        self.balances = after;
+        Ok(())
+    }
+}
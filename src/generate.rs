@@ -0,0 +1,170 @@
+//! Synthetic transaction files for load testing and demos.
+//!
+//! Disputes always refer to a real prior deposit of the same client, and resolves and chargebacks
+//! to an open dispute, so that they exercise the dispute paths of [crate::process_tx].
+//! Deposits and withdrawals are only generated for clients that have not been charged back,
+//! as the default rules lock those accounts, so every generated deposit can be disputed.
+//! The same settings and seed always generate the same file.
+
+use std::io::Write;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::Decimal;
+
+/// Decimal places of generated amounts
+const AMOUNT_SCALE: u32 = 4;
+/// Largest generated amount, in units of the smallest decimal place
+const MAX_AMOUNT: i64 = 10_000_000;
+
+/// Settings of a generated transaction file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateConfig {
+    /// Number of clients, with IDs starting at 1
+    pub clients: u16,
+    /// Number of rows, excluding the header
+    pub rows: u64,
+    /// Relative weight of deposits among deposits and withdrawals
+    pub deposit_ratio: f64,
+    /// Relative weight of withdrawals among deposits and withdrawals
+    pub withdrawal_ratio: f64,
+    /// Probability that a row disputes a prior deposit
+    pub dispute_probability: f64,
+    /// Probability that a row resolves an open dispute
+    pub resolve_probability: f64,
+    /// Probability that a row charges back an open dispute
+    pub chargeback_probability: f64,
+    /// Seed of the random number generator
+    pub seed: u64,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        Self {
+            clients: 100,
+            rows: 1000,
+            deposit_ratio: 0.6,
+            withdrawal_ratio: 0.4,
+            dispute_probability: 0.05,
+            resolve_probability: 0.02,
+            chargeback_probability: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+impl GenerateConfig {
+    /// Check that the settings can generate a file
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.clients == 0 {
+            anyhow::bail!("At least one client is required");
+        }
+        if self.rows > u64::from(u32::MAX) {
+            anyhow::bail!("At most {} rows can be generated", u32::MAX);
+        }
+        for (name, ratio) in [
+            ("Deposit ratio", self.deposit_ratio),
+            ("Withdrawal ratio", self.withdrawal_ratio),
+        ] {
+            if !(ratio >= 0.0 && ratio.is_finite()) {
+                anyhow::bail!("{name} must be a non-negative number, got {ratio}");
+            }
+        }
+        if self.deposit_ratio + self.withdrawal_ratio == 0.0 {
+            anyhow::bail!("Deposit and withdrawal ratios can not both be zero");
+        }
+        for (name, probability) in [
+            ("Dispute probability", self.dispute_probability),
+            ("Resolve probability", self.resolve_probability),
+            ("Chargeback probability", self.chargeback_probability),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                anyhow::bail!("{name} must be between 0 and 1, got {probability}");
+            }
+        }
+        if self.dispute_probability + self.resolve_probability + self.chargeback_probability > 1.0 {
+            anyhow::bail!(
+                "Dispute, resolve and chargeback probabilities must not exceed 1 in total"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Deposit or dispute a generated row refers to
+#[derive(Debug, Clone, Copy)]
+struct TxRef {
+    client: u16,
+    tx: u32,
+}
+
+/// Write a generated transaction file as CSV
+pub fn generate<W: Write>(writer: W, config: &GenerateConfig) -> anyhow::Result<()> {
+    config.validate()?;
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["type", "client", "tx", "amount"])?;
+
+    // deposits that can be disputed, and disputes that can be resolved or charged back
+    let mut deposits: Vec<TxRef> = Vec::new();
+    let mut disputes: Vec<TxRef> = Vec::new();
+    // clients whose accounts are not locked by a chargeback
+    let mut unlocked: Vec<u16> = (1..=config.clients).collect();
+    let deposit_share = config.deposit_ratio / (config.deposit_ratio + config.withdrawal_ratio);
+    let mut next_tx: u32 = 1;
+
+    for _ in 0..config.rows {
+        let roll: f64 = rng.random();
+        let chargeback = config.chargeback_probability;
+        let resolve = chargeback + config.resolve_probability;
+        let dispute = resolve + config.dispute_probability;
+
+        if roll < resolve && !disputes.is_empty() {
+            let index = rng.random_range(0..disputes.len());
+            let open = disputes.swap_remove(index);
+            // the last unlocked client is never charged back, so that rows can still be generated
+            let name = if roll < chargeback && unlocked != [open.client] {
+                unlocked.retain(|client| *client != open.client);
+                "chargeback"
+            } else {
+                // a resolved deposit can be disputed again
+                deposits.push(open);
+                "resolve"
+            };
+            write_dispute(&mut writer, name, open)?;
+        } else if roll < dispute && !deposits.is_empty() {
+            let index = rng.random_range(0..deposits.len());
+            let deposit = deposits.swap_remove(index);
+            disputes.push(deposit);
+            write_dispute(&mut writer, "dispute", deposit)?;
+        } else {
+            let client = unlocked[rng.random_range(0..unlocked.len())];
+            let tx = next_tx;
+            next_tx += 1;
+            let amount = Decimal::new(rng.random_range(1..=MAX_AMOUNT), AMOUNT_SCALE);
+            let name = if rng.random_bool(deposit_share) {
+                deposits.push(TxRef { client, tx });
+                "deposit"
+            } else {
+                "withdrawal"
+            };
+            writer.write_record([
+                name,
+                &client.to_string(),
+                &tx.to_string(),
+                &amount.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_dispute<W: Write>(
+    writer: &mut csv::Writer<W>,
+    name: &str,
+    tx: TxRef,
+) -> anyhow::Result<()> {
+    writer.write_record([name, &tx.client.to_string(), &tx.tx.to_string(), ""])?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Per-transaction journal with running balances.
+//!
+//! The journal is written while the input is processed, one row per transaction,
+//! so that every final balance can be traced back to the transactions that produced it.
+
+use std::io::Write;
+
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::types::{Accounts, Balances, TxOutcome};
+use crate::{TxEvent, TxObserver};
+
+/// Journal row, with the balances of the client after the transaction
+#[derive(Debug, Serialize)]
+pub struct JournalRow {
+    pub line: u64,
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: &'static str,
+    /// `applied` or `ignored`
+    pub outcome: &'static str,
+    /// Reason for ignoring the transaction
+    pub reason: Option<String>,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl JournalRow {
+    /// Build the journal row of a processed transaction
+    pub fn new(event: &TxEvent<'_>, accounts: &Accounts) -> Self {
+        let client = event.tx.client();
+        // transactions of unknown clients leave no account behind
+        let balances = Balances::of(accounts, client);
+        let (outcome, reason) = match event.outcome {
+            TxOutcome::Applied => ("applied", None),
+            TxOutcome::Ignored(reason) => ("ignored", Some(reason.to_string())),
+        };
+        Self {
+            line: event.line,
+            tx: event.tx.tx(),
+            client,
+            tx_type: event.tx.name(),
+            outcome,
+            reason,
+            available: balances.available.round_dp(4),
+            held: balances.held.round_dp(4),
+            total: balances.total.round_dp(4),
+            locked: balances.locked,
+        }
+    }
+}
+
+/// Writes a CSV journal row for every processed transaction
+pub struct JournalWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> JournalWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: WriterBuilder::new().has_headers(true).from_writer(writer),
+        }
+    }
+
+    /// Flush the journal and return the underlying writer
+    pub fn finish(self) -> anyhow::Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|err| err.into_error().into())
+    }
+}
+
+impl<W: Write> TxObserver for JournalWriter<W> {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        self.writer.serialize(JournalRow::new(event, accounts))?;
+        Ok(())
+    }
+}
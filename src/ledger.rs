@@ -0,0 +1,280 @@
+//! Double-entry general ledger export.
+//!
+//! Every applied transaction is posted as a balanced entry that debits one ledger account
+//! and credits another by the same amount. Client accounts are liabilities of the platform,
+//! so their balances are credit balances. A chargeback that leaves a client with a negative total
+//! is also written off as a loss, as the platform has paid the issuer more than the client held.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::types::{Accounts, ClientAccount, TxOutcome, TxType};
+use crate::{TxEvent, TxObserver};
+
+/// Account of the general ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LedgerAccount {
+    /// Funds received from and paid out to clients, or reversed to the card issuer by chargebacks
+    SettlementCash,
+    /// Shortfalls of chargebacks that the platform bears
+    ChargebackLosses,
+    /// Available funds of a client
+    ClientAvailable(u16),
+    /// Held funds of a client
+    ClientHeld(u16),
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::SettlementCash => write!(f, "settlement_cash"),
+            LedgerAccount::ChargebackLosses => write!(f, "chargeback_losses"),
+            LedgerAccount::ClientAvailable(client) => write!(f, "client:{client}:available"),
+            LedgerAccount::ClientHeld(client) => write!(f, "client:{client}:held"),
+        }
+    }
+}
+
+/// Balanced ledger entry of an applied transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub line: u64,
+    pub tx: u32,
+    pub tx_type: &'static str,
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    pub amount: Decimal,
+}
+
+impl LedgerEntry {
+    /// Build the entry of a processed transaction, none if it was ignored
+    pub fn new(event: &TxEvent<'_>, accounts: &Accounts) -> Option<Self> {
+        if event.outcome != TxOutcome::Applied {
+            return None;
+        }
+
+        let client = event.tx.client();
+        let (debit, credit, amount) = match event.tx {
+            TxType::Deposit(change) => (
+                LedgerAccount::SettlementCash,
+                LedgerAccount::ClientAvailable(client),
+                change.amount,
+            ),
+            TxType::Withdrawal(change) => (
+                LedgerAccount::ClientAvailable(client),
+                LedgerAccount::SettlementCash,
+                change.amount,
+            ),
+            // disputes move the amount of the deposit
+            TxType::Dispute(dispute) | TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                let amount = accounts
+                    .get(&dispute.client)?
+                    .deposit_txs
+                    .get(&dispute.tx)?
+                    .amount;
+                let (debit, credit) = match event.tx {
+                    TxType::Dispute(_) => (
+                        LedgerAccount::ClientAvailable(client),
+                        LedgerAccount::ClientHeld(client),
+                    ),
+                    TxType::Resolve(_) => (
+                        LedgerAccount::ClientHeld(client),
+                        LedgerAccount::ClientAvailable(client),
+                    ),
+                    // the held funds are paid back to the card issuer
+                    _ => (
+                        LedgerAccount::ClientHeld(client),
+                        LedgerAccount::SettlementCash,
+                    ),
+                };
+                (debit, credit, amount)
+            }
+        };
+
+        Some(Self {
+            line: event.line,
+            tx: event.tx.tx(),
+            tx_type: event.tx.name(),
+            debit,
+            credit,
+            amount,
+        })
+    }
+
+    /// Build the entry writing off the shortfall of an applied chargeback,
+    /// none if the client still covers the charged back amount
+    pub fn write_off(event: &TxEvent<'_>, accounts: &Accounts) -> Option<Self> {
+        let TxType::Chargeback(dispute) = event.tx else {
+            return None;
+        };
+        if event.outcome != TxOutcome::Applied {
+            return None;
+        }
+
+        let account = accounts.get(&dispute.client)?;
+        let amount = account.deposit_txs.get(&dispute.tx)?.amount;
+        // only the part of the negative total caused by this chargeback
+        let before = account.total.saturating_add(amount).min(Decimal::ZERO);
+        let shortfall = before.saturating_sub(account.total.min(Decimal::ZERO));
+        if shortfall <= Decimal::ZERO {
+            return None;
+        }
+
+        Some(Self {
+            line: event.line,
+            tx: event.tx.tx(),
+            tx_type: event.tx.name(),
+            debit: LedgerAccount::ChargebackLosses,
+            credit: LedgerAccount::ClientAvailable(dispute.client),
+            amount: shortfall,
+        })
+    }
+}
+
+/// Balances of all ledger accounts, as debits minus credits
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TrialBalance {
+    balances: BTreeMap<LedgerAccount, Decimal>,
+}
+
+impl TrialBalance {
+    /// Post an entry to the debited and credited accounts
+    pub fn post(&mut self, entry: &LedgerEntry) {
+        let debit = self.balances.entry(entry.debit).or_default();
+        *debit = debit.saturating_add(entry.amount);
+        let credit = self.balances.entry(entry.credit).or_default();
+        *credit = credit.saturating_sub(entry.amount);
+    }
+
+    /// Debit balance of an account, negative for a credit balance
+    pub fn balance(&self, account: LedgerAccount) -> Decimal {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Sum of all balances, zero as every entry is balanced
+    pub fn total(&self) -> Decimal {
+        self.balances
+            .values()
+            .fold(Decimal::ZERO, |sum, balance| sum.saturating_add(*balance))
+    }
+
+    /// Balances of all accounts that have been posted to
+    pub fn iter(&self) -> impl Iterator<Item = (LedgerAccount, Decimal)> + '_ {
+        self.balances
+            .iter()
+            .map(|(account, balance)| (*account, *balance))
+    }
+}
+
+/// Ledger row for csv output, one debit or credit line of an entry
+#[derive(Debug, Serialize)]
+struct LedgerRow {
+    entry: u64,
+    line: u64,
+    tx: u32,
+    #[serde(rename = "type")]
+    tx_type: &'static str,
+    account: String,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    debit: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    credit: Option<Decimal>,
+}
+
+/// Writes the ledger entry of every applied transaction as CSV debit and credit lines,
+/// keeping the trial balance of the posted entries
+pub struct LedgerWriter<W: Write> {
+    writer: csv::Writer<W>,
+    entries: u64,
+    trial_balance: TrialBalance,
+}
+
+impl<W: Write> LedgerWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: WriterBuilder::new().has_headers(true).from_writer(writer),
+            entries: 0,
+            trial_balance: TrialBalance::default(),
+        }
+    }
+
+    /// Trial balance of the entries written so far
+    pub fn trial_balance(&self) -> &TrialBalance {
+        &self.trial_balance
+    }
+
+    /// Flush the ledger and return the underlying writer
+    pub fn finish(self) -> anyhow::Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|err| err.into_error().into())
+    }
+
+    /// Post the balances of accounts that existed before processing, such as opening balances,
+    /// as entries from settlement cash. Negative balances are posted the other way round.
+    pub fn post_opening(&mut self, accounts: &Accounts) -> anyhow::Result<()> {
+        // in client order, so that the same accounts always give the same ledger
+        let mut clients: Vec<(&u16, &ClientAccount)> = accounts.iter().collect();
+        clients.sort_by_key(|(client, _)| **client);
+        for (client, account) in clients {
+            for (balance, amount) in [
+                (LedgerAccount::ClientAvailable(*client), account.available),
+                (LedgerAccount::ClientHeld(*client), account.held),
+            ] {
+                let (debit, credit) = match amount.is_sign_negative() {
+                    false => (LedgerAccount::SettlementCash, balance),
+                    true => (balance, LedgerAccount::SettlementCash),
+                };
+                if !amount.is_zero() {
+                    self.write_entry(&LedgerEntry {
+                        line: 0,
+                        tx: 0,
+                        tx_type: "opening",
+                        debit,
+                        credit,
+                        amount: amount.abs(),
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the debit and credit lines of an entry and post it to the trial balance
+    fn write_entry(&mut self, entry: &LedgerEntry) -> anyhow::Result<()> {
+        self.entries += 1;
+        let row = |account: LedgerAccount, debit, credit| LedgerRow {
+            entry: self.entries,
+            line: entry.line,
+            tx: entry.tx,
+            tx_type: entry.tx_type,
+            account: account.to_string(),
+            debit,
+            credit,
+        };
+        self.writer
+            .serialize(row(entry.debit, Some(entry.amount), None))?;
+        self.writer
+            .serialize(row(entry.credit, None, Some(entry.amount)))?;
+        self.trial_balance.post(entry);
+        Ok(())
+    }
+}
+
+impl<W: Write> TxObserver for LedgerWriter<W> {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        let Some(entry) = LedgerEntry::new(event, accounts) else {
+            return Ok(());
+        };
+        self.write_entry(&entry)?;
+        if let Some(write_off) = LedgerEntry::write_off(event, accounts) {
+            self.write_entry(&write_off)?;
+        }
+        Ok(())
+    }
+}
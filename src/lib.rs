@@ -1,63 +1,341 @@
+pub mod config;
+pub mod control;
+pub mod diff;
+pub mod disputes;
+pub mod explain;
+pub mod generate;
+pub mod journal;
+pub mod ledger;
+pub mod opening;
+pub mod output;
+pub mod parallel;
+pub mod parse;
+pub mod progress;
+pub mod repl;
+pub mod settings;
+pub mod statement;
+pub mod stats;
 pub mod types;
+pub mod validate;
 
 use anyhow::Context;
-use csv::{Reader, ReaderBuilder, WriterBuilder};
+use csv::{Reader, ReaderBuilder};
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use output::{AccountWriter, account_writer};
+use parse::{AmountFormat, ByteRecordIter, Columns, is_trailer, parse_trailer};
+use rust_decimal::Decimal;
+use std::time::Instant;
 use types::TxType;
 
-use crate::types::{AccountRow, Accounts, CsvRow, DisputeState};
+use crate::config::{InputConfig, OutputConfig, ProcessConfig, Rules};
+use crate::control::ControlTotal;
+use crate::progress::{PROGRESS_ROWS, Progress};
+use crate::types::{
+    AccountRow, Accounts, ClientAccount, CsvRow, DisputeState, IgnoreReason, TxOutcome,
+};
 
+// the baseline tests compare booleans and map lookups explicitly
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::unnecessary_get_then_check)]
 mod tests;
 
-/// Print accounts as CSV to STDOUT
-pub fn print_accounts(accounts: &mut Accounts) -> anyhow::Result<()> {
-    let mut writer = WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(std::io::stdout());
-
-    // Write all rows with headers
-    for entry in accounts {
-        let id = entry.0;
-        let account = entry.1;
-
-        // normalise amounts to 4dp
-        account.normalize();
-        let account_row = AccountRow {
-            client: *id,
-            available: account.available,
-            held: account.held,
-            total: account.total,
-            locked: account.locked,
-        };
+/// Print accounts to STDOUT in the configured format
+pub fn print_accounts(accounts: &Accounts, output: &OutputConfig) -> anyhow::Result<()> {
+    write_accounts(std::io::stdout().lock(), accounts, output)
+}
 
-        writer.serialize(account_row)?;
+/// Write accounts to any writer in the configured format
+pub fn write_accounts<W: std::io::Write>(
+    writer: W,
+    accounts: &Accounts,
+    output: &OutputConfig,
+) -> anyhow::Result<()> {
+    let mut writer = account_writer(output, writer);
+
+    for account_row in account_rows(accounts, output) {
+        writer.write_row(&account_row)?;
     }
 
-    writer.flush()?;
-    Ok(())
+    writer.finish()
+}
+
+/// Convert accounts into output rows in the configured order.
+/// Amounts are rounded in the rows only, the accounts are left untouched.
+pub fn account_rows(accounts: &Accounts, output: &OutputConfig) -> Vec<AccountRow> {
+    let mut rows: Vec<AccountRow> = accounts
+        .iter()
+        .map(|(id, account)| account_row(*id, account, output))
+        .collect();
+
+    output.order.sort(&mut rows);
+    rows
+}
+
+/// Write only the accounts changed since the last flush, and clear the dirty set.
+/// Returns the number of accounts written.
+pub fn flush_accounts(
+    writer: &mut dyn AccountWriter,
+    accounts: &mut Accounts,
+    output: &OutputConfig,
+) -> anyhow::Result<usize> {
+    let mut rows: Vec<AccountRow> = accounts
+        .take_dirty()
+        .into_iter()
+        .filter_map(|id| Some(account_row(id, accounts.get(&id)?, output)))
+        .collect();
+
+    output.order.sort(&mut rows);
+    for row in &rows {
+        writer.write_row(row)?;
+    }
+    Ok(rows.len())
+}
+
+/// Output row of an account, rounded to the output precision
+fn account_row(client: u16, account: &ClientAccount, output: &OutputConfig) -> AccountRow {
+    let round = |amount| output.rounding.round(amount, output.precision);
+    AccountRow {
+        client,
+        available: round(account.available),
+        held: round(account.held),
+        total: round(account.total),
+        locked: account.locked,
+    }
 }
 
 /// Process all transactions lazily from an iterator
 pub fn process_txs<R: std::io::Read>(reader: &mut Reader<R>, accounts: &mut Accounts) {
-    let txs = record_iter(reader);
-    for tx_res in txs {
-        // ignore malformed entries
-        let Ok(tx) = tx_res else {
-            continue;
+    // ignore input without the expected headers
+    let _ = process_txs_with(reader, accounts, &ProcessConfig::default(), &mut ());
+}
+
+/// A processed transaction, passed to a [TxObserver]
+#[derive(Debug)]
+pub struct TxEvent<'a> {
+    /// Line of the transaction in the input
+    pub line: u64,
+    pub tx: &'a TxType,
+    pub outcome: TxOutcome,
+}
+
+impl TxEvent<'_> {
+    /// Amount of the transaction, or of the deposit referred to by a dispute
+    pub fn amount(&self, accounts: &Accounts) -> Option<Decimal> {
+        match self.tx {
+            TxType::Deposit(change) | TxType::Withdrawal(change) => Some(change.amount),
+            TxType::Dispute(dispute) | TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                accounts
+                    .get(&dispute.client)
+                    .and_then(|account| account.deposit_txs.get(&dispute.tx))
+                    .map(|deposit| deposit.amount)
+            }
+        }
+    }
+}
+
+/// Observes transactions as they are processed by [process_txs_with]
+pub trait TxObserver {
+    /// Called after every transaction, with the accounts in their updated state
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()>;
+
+    /// Called after [on_tx](TxObserver::on_tx) of every observer, to flush changed accounts
+    fn after_tx(&mut self, _accounts: &mut Accounts) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called every [PROGRESS_ROWS] rows and once at the end of the input
+    fn on_progress(&mut self, _progress: &Progress) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// No-op observer
+impl TxObserver for () {
+    fn on_tx(&mut self, _event: &TxEvent<'_>, _accounts: &Accounts) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: TxObserver + ?Sized> TxObserver for &mut T {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        (**self).on_tx(event, accounts)
+    }
+
+    fn after_tx(&mut self, accounts: &mut Accounts) -> anyhow::Result<()> {
+        (**self).after_tx(accounts)
+    }
+
+    fn on_progress(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        (**self).on_progress(progress)
+    }
+}
+
+/// Optional observer, a no-op if absent
+impl<T: TxObserver> TxObserver for Option<T> {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        match self {
+            Some(observer) => observer.on_tx(event, accounts),
+            None => Ok(()),
+        }
+    }
+
+    fn after_tx(&mut self, accounts: &mut Accounts) -> anyhow::Result<()> {
+        match self {
+            Some(observer) => observer.after_tx(accounts),
+            None => Ok(()),
+        }
+    }
+
+    fn on_progress(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        match self {
+            Some(observer) => observer.on_progress(progress),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Pair of observers, called in order
+impl<A: TxObserver, B: TxObserver> TxObserver for (A, B) {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        self.0.on_tx(event, accounts)?;
+        self.1.on_tx(event, accounts)
+    }
+
+    fn after_tx(&mut self, accounts: &mut Accounts) -> anyhow::Result<()> {
+        self.0.after_tx(accounts)?;
+        self.1.after_tx(accounts)
+    }
+
+    fn on_progress(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        self.0.on_progress(progress)?;
+        self.1.on_progress(progress)
+    }
+}
+
+/// Summary of the rows read by [process_txs_with]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcessSummary {
+    /// Number of rows read, excluding the header and the trailer
+    pub rows: u64,
+    /// Number of rows that failed to parse
+    pub malformed: u64,
+    /// Number of transactions ignored by the engine, e.g. for insufficient funds
+    pub ignored: u64,
+    /// Sum of amounts of all deposits and withdrawals
+    pub amount: Decimal,
+    /// Mismatch of the control totals, if mismatches only warn
+    pub control_warning: Option<String>,
+}
+
+/// Process all transactions lazily with the given settings, reporting each one to the observer.
+/// Fails if the input has no valid headers, if the observer fails,
+/// or if the control totals do not match.
+/// In strict mode, also fails on the first malformed row or ignored transaction,
+/// and any failure leaves the accounts unchanged.
+pub fn process_txs_with<R: std::io::Read>(
+    reader: &mut Reader<R>,
+    accounts: &mut Accounts,
+    config: &ProcessConfig,
+    observer: &mut dyn TxObserver,
+) -> anyhow::Result<ProcessSummary> {
+    let before = config.strict.then(|| accounts.clone());
+    let result = process_rows(reader, accounts, config, observer);
+    if let (Err(_), Some(before)) = (&result, before) {
+        *accounts = before;
+    }
+    result
+}
+
+/// Process all rows of the input, see [process_txs_with]
+fn process_rows<R: std::io::Read>(
+    reader: &mut Reader<R>,
+    accounts: &mut Accounts,
+    config: &ProcessConfig,
+    observer: &mut dyn TxObserver,
+) -> anyhow::Result<ProcessSummary> {
+    let start = Instant::now();
+    let mut summary = ProcessSummary::default();
+    let mut txs = ByteRecordIter::new(reader, config.input.amount.clone())?;
+    loop {
+        if summary.rows > 0 && summary.rows % PROGRESS_ROWS == 0 {
+            let progress = Progress::new(&summary, txs.bytes(), start.elapsed(), false);
+            observer.on_progress(&progress)?;
+        }
+        let Some(tx_res) = txs.next() else {
+            break;
+        };
+        summary.rows += 1;
+        let tx = match tx_res {
+            Ok(tx) => tx,
+            Err(err) if config.strict => {
+                return Err(err.context(format!("Rejected line {}", txs.line())));
+            }
+            // ignore malformed entries
+            Err(_) => {
+                summary.malformed += 1;
+                continue;
+            }
+        };
+
+        if let TxType::Deposit(change) | TxType::Withdrawal(change) = &tx {
+            summary.amount = summary.amount.saturating_add(change.amount);
+        }
+        let outcome = process_tx_with(tx.clone(), accounts, &config.rules);
+        if outcome != TxOutcome::Applied {
+            if config.strict {
+                anyhow::bail!(
+                    "Rejected line {}: {} tx {} {outcome}",
+                    txs.line(),
+                    tx.name(),
+                    tx.tx()
+                );
+            }
+            summary.ignored += 1;
+        }
+        let event = TxEvent {
+            line: txs.line(),
+            tx: &tx,
+            outcome,
         };
+        observer.on_tx(&event, accounts)?;
+        observer.after_tx(accounts)?;
+    }
+    let progress = Progress::new(&summary, txs.bytes(), start.elapsed(), true);
+    observer.on_progress(&progress)?;
+
+    let read = ControlTotal {
+        records: summary.rows,
+        amount: summary.amount,
+    };
+    summary.control_warning = config.control.check(&read, txs.trailer())?;
+    Ok(summary)
+}
 
-        process_tx(tx, accounts);
+/// Process individual transaction with the default rules
+pub fn process_tx(tx: TxType, accounts: &mut Accounts) -> TxOutcome {
+    process_tx_with(tx, accounts, &Rules::default())
+}
+
+/// Process individual transaction, marking the account of the client dirty if it changed
+pub fn process_tx_with(tx: TxType, accounts: &mut Accounts, rules: &Rules) -> TxOutcome {
+    let client = tx.client();
+    let existed = accounts.contains_key(&client);
+    let outcome = apply_tx(tx, accounts, rules);
+    // accounts opened by an ignored transaction are new to the output as well
+    if outcome == TxOutcome::Applied || (!existed && accounts.contains_key(&client)) {
+        accounts.mark_dirty(client);
     }
+    outcome
 }
 
-/// Process individual transaction
-pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
+/// Update the balances for a transaction
+fn apply_tx(tx: TxType, accounts: &mut Accounts, rules: &Rules) -> TxOutcome {
     match tx {
         TxType::Deposit(balance_change) => {
             let account = accounts.entry(balance_change.client).or_default();
             // if account is locked, ignore
             if account.locked {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::AccountLocked);
             }
 
             account.available = account.available.saturating_add(balance_change.amount);
@@ -72,12 +350,12 @@ pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
             let account = accounts.entry(balance_change.client).or_default();
             // if account is locked, ignore
             if account.locked {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::AccountLocked);
             }
 
             // if available balance is lower than withdrawal amount, ignore tx
             if account.available < balance_change.amount {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::InsufficientFunds);
             }
 
             account.available = account.available.saturating_sub(balance_change.amount);
@@ -86,17 +364,22 @@ pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
         TxType::Dispute(dispute) => {
             // if account doesn't exist, there is nothing to dispute
             let Some(account) = accounts.get_mut(&dispute.client) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownAccount);
             };
 
             // if deposit tx doesn't exit, there is nothing to dispute
             let Some(deposit) = account.deposit_txs.get_mut(&dispute.tx) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownTx);
             };
 
             // if deposit tx is already disputed, we ignore it
             if deposit.dispute != DisputeState::None {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::AlreadyDisputed);
+            }
+
+            // if the rules require it, the disputed amount must still be available
+            if rules.dispute_requires_funds && account.available < deposit.amount {
+                return TxOutcome::Ignored(IgnoreReason::InsufficientFunds);
             }
 
             // update balances and tx status
@@ -107,17 +390,17 @@ pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
         TxType::Resolve(dispute) => {
             // if account doesn't exist, there is nothing to resolve
             let Some(account) = accounts.get_mut(&dispute.client) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownAccount);
             };
 
             // if deposit tx doesn't exit, there is nothing to resolve
             let Some(deposit) = account.deposit_txs.get_mut(&dispute.tx) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownTx);
             };
 
             // if deposit tx isn't disputed, we ignore it
             if deposit.dispute != DisputeState::Disputed {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::NotDisputed);
             }
 
             // update balances and tx status
@@ -129,17 +412,17 @@ pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
         TxType::Chargeback(dispute) => {
             // if account doesn't exist, there is nothing to chargeback
             let Some(account) = accounts.get_mut(&dispute.client) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownAccount);
             };
 
             // if deposit tx doesn't exit, there is nothing to chargeback
             let Some(deposit) = account.deposit_txs.get_mut(&dispute.tx) else {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::UnknownTx);
             };
 
             // if deposit tx isn't disputed, we ignore it
             if deposit.dispute != DisputeState::Disputed {
-                return;
+                return TxOutcome::Ignored(IgnoreReason::NotDisputed);
             }
 
             // update balances and tx status
@@ -148,26 +431,87 @@ pub fn process_tx(tx: TxType, accounts: &mut Accounts) {
             // transaction is no longer disputed
             deposit.dispute = DisputeState::Chargeback;
             // Lock the account
-            account.locked = true;
+            if rules.lock_on_chargeback {
+                account.locked = true;
+            }
         }
     }
+
+    TxOutcome::Applied
 }
 
-/// Return an iterator of parsed transaction records
+/// Return an iterator of parsed transaction records.
+/// Valid trailer rows are skipped, and input without the expected headers yields a single error.
 pub fn record_iter<R: std::io::Read>(
     reader: &mut Reader<R>,
 ) -> impl Iterator<Item = anyhow::Result<TxType>> {
-    reader.deserialize::<CsvRow>().map(|res| {
-        res.context("Failed to deserialize the record")
-            .and_then(TxType::try_from)
-    })
+    let (txs, error) = match numbered_record_iter(reader, &AmountFormat::default()) {
+        Ok(txs) => (Some(txs), None),
+        Err(err) => (None, Some(Err(err))),
+    };
+    txs.into_iter().flatten().map(|(_, tx)| tx).chain(error)
 }
 
+/// Return an iterator of parsed transaction records with their line in the input,
+/// parsing amounts in the given format. Yields the same transactions as [record_iter].
+/// Valid trailer rows are skipped, like in [ByteRecordIter].
+pub fn numbered_record_iter<'r, R: std::io::Read>(
+    reader: &'r mut Reader<R>,
+    format: &AmountFormat,
+) -> anyhow::Result<impl Iterator<Item = (u64, anyhow::Result<TxType>)> + use<'r, R>> {
+    let headers = reader.headers().context("Failed to read headers")?.clone();
+    let columns = Columns::from_headers(headers.as_byte_record())?;
+    let format = format.clone();
+    Ok(reader.records().filter_map(move |res| {
+        let record = match res {
+            Ok(record) => record,
+            Err(err) => {
+                let line = err.position().map_or(0, |pos| pos.line());
+                return Some((line, Err(err).context("Failed to read the record")));
+            }
+        };
+        let line = record.position().map_or(0, |pos| pos.line());
+        if is_trailer(record.as_byte_record(), &columns) {
+            let trailer = parse_trailer(record.as_byte_record(), &columns, &format);
+            return trailer.err().map(|err| (line, Err(err)));
+        }
+        let tx = record
+            .deserialize::<CsvRow>(Some(&headers))
+            .context("Failed to deserialize the record")
+            .and_then(|row| TxType::from_row(row, &format));
+        Some((line, tx))
+    }))
+}
+
+/// Return an iterator of parsed transaction records that avoids per-row allocations.
+/// Yields the same transactions as [record_iter].
+pub fn byte_record_iter<R: std::io::Read>(
+    reader: &mut Reader<R>,
+) -> anyhow::Result<ByteRecordIter<'_, R>> {
+    ByteRecordIter::new(reader, AmountFormat::default())
+}
+
+/// Input file transcoded to UTF-8, with any byte order mark stripped
+pub type DecodedFile = DecodeReaderBytes<std::fs::File, Vec<u8>>;
+
 /// Construct file reader for CSV input.
-pub fn path_reader(path: &str) -> anyhow::Result<Reader<std::fs::File>> {
-    ReaderBuilder::new()
+pub fn path_reader(path: &str) -> anyhow::Result<Reader<DecodedFile>> {
+    path_reader_with(path, &InputConfig::default())
+}
+
+/// Construct file reader for CSV input in the configured encoding.
+pub fn path_reader_with(path: &str, input: &InputConfig) -> anyhow::Result<Reader<DecodedFile>> {
+    let file = std::fs::File::open(path).context(format!("Failed to open {path}"))?;
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(input.encoding.encoding())
+        // a BOM identifies the encoding better than the configuration
+        .bom_override(true)
+        .strip_bom(true)
+        // UTF-8 is validated by the CSV reader
+        .utf8_passthru(true)
+        .build(file);
+    Ok(ReaderBuilder::new()
         .has_headers(true)
         .trim(csv::Trim::All)
-        .from_path(path)
-        .context(format!("Failed to open {path}"))
+        .from_reader(decoder))
 }
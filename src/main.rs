@@ -1,12 +1,652 @@
-use tx_engine::{path_reader, print_accounts, process_txs, types::Accounts};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let path = &args[1];
-    let mut reader = path_reader(path).unwrap();
+use anyhow::Context;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use rust_decimal::Decimal;
+use tx_engine::{
+    ProcessSummary,
+    config::{AccountOrder, InputConfig, InputEncoding, OutputConfig, ProcessConfig, Rounding},
+    control::{ControlSource, MismatchAction},
+    diff::{diff_accounts, read_snapshot},
+    disputes::{DisputeReport, DisputeTracker},
+    explain::Explainer,
+    generate::GenerateConfig,
+    journal::JournalWriter,
+    ledger::LedgerWriter,
+    numbered_record_iter,
+    opening::read_opening,
+    output::{AtomicFile, DeltaWriter, OutputFormat, write_atomic},
+    parallel::process_txs_mmap,
+    parse::AmountFormat,
+    path_reader_with, print_accounts, process_txs_with,
+    progress::ProgressReporter,
+    repl::Repl,
+    settings::Settings,
+    statement::{StatementCollector, StatementFormat},
+    stats::{LARGEST_TXS, StatsCollector, StatsFormat},
+    types::Accounts,
+    write_accounts,
+};
+
+/// Exit code when an input or output failed, the control totals did not match, or a strict run rejected a row
+const EXIT_ERROR: u8 = 1;
+/// Exit code when some input rows were rejected, or failed validation
+const EXIT_REJECTED: u8 = 3;
+/// Exit code when two account snapshots differ
+const EXIT_DIFFERENT: u8 = 4;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  An input or output could not be read or written, the control totals did not match, or --strict rejected a row
+  2  Invalid usage
+  3  Some input rows were rejected, the remaining rows were processed, or validation found problems
+  4  The account snapshots differ (diff)";
+
+/// Process client transactions into account balances
+#[derive(Parser)]
+#[command(version, after_help = EXIT_CODES, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// TOML file with the input dialect, output and dispute rules, overridden by flags
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process transactions and write the resulting accounts, the default command
+    Process(ProcessArgs),
+    /// Check a transaction file without applying it
+    Validate(InputArgs),
+    /// Summarize a transaction file
+    Stats(StatsArgs),
+    /// Trace how a client reached its final state
+    Explain(ExplainArgs),
+    /// Explore and change accounts interactively
+    Repl(ReplArgs),
+    /// Compare two account snapshots in CSV, JSON or NDJSON
+    Diff(DiffArgs),
+    /// Write a synthetic transaction file
+    Generate(GenerateArgs),
+}
+
+/// Input file and its dialect
+#[derive(Args)]
+struct InputArgs {
+    /// Transaction file in CSV
+    path: String,
+    /// Character encoding of the input: utf8, latin1 or windows1252
+    #[arg(long)]
+    encoding: Option<InputEncoding>,
+    /// Amounts are integers in minor units, with this many decimal places
+    #[arg(long, value_name = "EXPONENT", value_parser = scale_parser())]
+    minor_units: Option<u32>,
+    /// Amounts use a decimal comma instead of a decimal point
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Thousands separator of amounts
+    #[arg(long, value_name = "CHAR")]
+    thousands_separator: Option<char>,
+}
+
+impl InputArgs {
+    fn config(&self, settings: &Settings) -> anyhow::Result<InputConfig> {
+        let mut input = settings.input.clone();
+        if let Some(encoding) = self.encoding {
+            input.encoding = encoding;
+        }
+        let amount = &settings.input.amount;
+        let decimal_separator = match self.decimal_comma {
+            true => ',',
+            false => amount.decimal_separator,
+        };
+        input.amount = AmountFormat::new(
+            self.minor_units.or(amount.minor_units),
+            decimal_separator,
+            self.thousands_separator.or(amount.thousands_separator),
+        )?;
+        Ok(input)
+    }
+}
+
+#[derive(Args)]
+struct ProcessArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Start from the balances of an accounts file in CSV, JSON or NDJSON, e.g. of the previous run
+    #[arg(long, value_name = "PATH")]
+    opening: Option<PathBuf>,
+    /// Memory-map the input and parse it in parallel chunks
+    #[arg(long, conflicts_with_all = [
+        "control_trailer", "control_file", "journal", "ledger", "disputes", "statement", "flush_every",
+        "progress", "strict",
+    ])]
+    mmap: bool,
+    /// Check the input against the control totals of its trailer row
+    #[arg(long)]
+    control_trailer: bool,
+    /// Check the input against the control totals of a sidecar CSV file
+    #[arg(long, value_name = "PATH", conflicts_with = "control_trailer")]
+    control_file: Option<PathBuf>,
+    /// Warn instead of failing when the control totals do not match
+    #[arg(long)]
+    control_warn: bool,
+    /// Write the output to a file instead of stdout, replacing it atomically
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Format of the accounts: csv, json or ndjson
+    #[arg(long, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+    /// Order of the accounts: client, total or locked
+    #[arg(long)]
+    order: Option<AccountOrder>,
+    /// Decimal places of output amounts
+    #[arg(long, value_name = "PLACES", value_parser = scale_parser())]
+    precision: Option<u32>,
+    /// Rounding of output amounts: half-even, half-up or truncate
+    #[arg(long)]
+    rounding: Option<Rounding>,
+    /// Write a CSV journal of every transaction with its outcome and running balances
+    #[arg(long, value_name = "PATH")]
+    journal: Option<PathBuf>,
+    /// Write a double-entry general ledger in CSV
+    #[arg(long, value_name = "PATH")]
+    ledger: Option<PathBuf>,
+    /// Write a CSV report of the open disputes
+    #[arg(long, value_name = "PATH")]
+    disputes: Option<PathBuf>,
+    /// Write the statement of a client instead of the accounts
+    #[arg(long, value_name = "CLIENT")]
+    statement: Option<u16>,
+    /// Format of the statement: text or csv
+    #[arg(long, value_name = "FORMAT", requires = "statement")]
+    statement_format: Option<StatementFormat>,
+    /// Write the accounts changed since the last flush to stdout after every N transactions
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["output", "statement"],
+    )]
+    flush_every: Option<u64>,
+    /// Report the progress and throughput on stderr
+    #[arg(long)]
+    progress: bool,
+    /// Fail without output on the first malformed row or ignored transaction
+    #[arg(long, conflicts_with = "flush_every")]
+    strict: bool,
+}
+
+impl ProcessArgs {
+    fn config(&self, settings: &Settings) -> anyhow::Result<ProcessConfig> {
+        let mut config = ProcessConfig {
+            input: self.input.config(settings)?,
+            rules: settings.rules,
+            strict: self.strict,
+            ..Default::default()
+        };
+        if self.control_trailer {
+            config.control.source = ControlSource::Trailer;
+        }
+        if let Some(path) = &self.control_file {
+            config.control.source = ControlSource::Sidecar(path.clone());
+        }
+        if self.control_warn {
+            config.control.on_mismatch = MismatchAction::Warn;
+        }
+        Ok(config)
+    }
+
+    fn output(&self, settings: &Settings) -> OutputConfig {
+        let mut output = settings.output.clone();
+        if let Some(format) = self.output_format {
+            output.format = format;
+        }
+        if let Some(order) = self.order {
+            output.order = order;
+        }
+        if let Some(precision) = self.precision {
+            output.precision = precision;
+        }
+        if let Some(rounding) = self.rounding {
+            output.rounding = rounding;
+        }
+        output
+    }
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Format of the summary: text or json
+    #[arg(long)]
+    format: Option<StatsFormat>,
+    /// Number of largest transactions to list
+    #[arg(long, value_name = "N", default_value_t = LARGEST_TXS)]
+    largest: usize,
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Client to explain
+    #[arg(long)]
+    client: u16,
+}
+
+#[derive(Args)]
+struct ReplArgs {
+    /// Transaction file in CSV to load first
+    path: Option<String>,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// Accounts of the earlier run
+    before: PathBuf,
+    /// Accounts of the later run
+    after: PathBuf,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    /// Number of clients [default: 100]
+    #[arg(long)]
+    clients: Option<u16>,
+    /// Number of rows [default: 1000]
+    #[arg(long)]
+    rows: Option<u64>,
+    /// Relative weight of deposits [default: 0.6]
+    #[arg(long, value_name = "RATIO")]
+    deposit_ratio: Option<f64>,
+    /// Relative weight of withdrawals [default: 0.4]
+    #[arg(long, value_name = "RATIO")]
+    withdrawal_ratio: Option<f64>,
+    /// Probability that a row disputes a prior deposit [default: 0.05]
+    #[arg(long, value_name = "PROBABILITY")]
+    dispute_probability: Option<f64>,
+    /// Probability that a row resolves an open dispute [default: 0.02]
+    #[arg(long, value_name = "PROBABILITY")]
+    resolve_probability: Option<f64>,
+    /// Probability that a row charges back an open dispute [default: 0.01]
+    #[arg(long, value_name = "PROBABILITY")]
+    chargeback_probability: Option<f64>,
+    /// Seed of the random number generator [default: 0]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Write the file instead of stdout, replacing it atomically
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+impl GenerateArgs {
+    fn config(&self) -> GenerateConfig {
+        let mut config = GenerateConfig::default();
+        if let Some(clients) = self.clients {
+            config.clients = clients;
+        }
+        if let Some(rows) = self.rows {
+            config.rows = rows;
+        }
+        if let Some(ratio) = self.deposit_ratio {
+            config.deposit_ratio = ratio;
+        }
+        if let Some(ratio) = self.withdrawal_ratio {
+            config.withdrawal_ratio = ratio;
+        }
+        if let Some(probability) = self.dispute_probability {
+            config.dispute_probability = probability;
+        }
+        if let Some(probability) = self.resolve_probability {
+            config.resolve_probability = probability;
+        }
+        if let Some(probability) = self.chargeback_probability {
+            config.chargeback_probability = probability;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = seed;
+        }
+        config
+    }
+}
+
+/// Parser of a number of decimal places supported by amounts
+fn scale_parser() -> clap::builder::RangedI64ValueParser<u32> {
+    clap::value_parser!(u32).range(..=i64::from(Decimal::MAX_SCALE))
+}
+
+/// Command line arguments, with `process` inserted before a bare input path
+fn args() -> Vec<OsString> {
+    with_default_command(std::env::args_os().collect())
+}
+
+/// Insert `process` after the leading global options unless they are followed by a subcommand
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut index = 1;
+    while let Some(arg) = args.get(index).and_then(|arg| arg.to_str()) {
+        match arg {
+            "--config" => index += 2,
+            _ if arg.starts_with("--config=") => index += 1,
+            _ => break,
+        }
+    }
+    let command = Cli::command();
+    let is_command = args
+        .get(index)
+        .and_then(|arg| arg.to_str())
+        .is_none_or(|arg| {
+            matches!(arg, "help" | "-h" | "--help" | "-V" | "--version")
+                || command.find_subcommand(arg).is_some()
+        });
+    if !is_command {
+        args.insert(index.min(args.len()), "process".into());
+    }
+    args
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse_from(args());
+    let result = cli
+        .config
+        .as_deref()
+        .map(Settings::from_path)
+        .transpose()
+        .and_then(|settings| {
+            let settings = settings.unwrap_or_default();
+            match &cli.command {
+                Command::Process(args) => process(args, &settings),
+                Command::Validate(args) => validate(args, &settings),
+                Command::Stats(args) => stats(args, &settings),
+                Command::Explain(args) => explain(args, &settings),
+                Command::Repl(args) => repl(args, &settings),
+                Command::Diff(args) => diff(args),
+                Command::Generate(args) => generate(args),
+            }
+        });
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Exit code of a processed input
+fn summary_code(summary: &ProcessSummary) -> ExitCode {
+    if let Some(warning) = &summary.control_warning {
+        eprintln!("Warning: {warning}");
+    }
+    if summary.malformed > 0 {
+        eprintln!(
+            "Warning: {} of {} rows were rejected",
+            summary.malformed, summary.rows
+        );
+        ExitCode::from(EXIT_REJECTED)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn process(args: &ProcessArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let config = args.config(settings)?;
+    let output = args.output(settings);
+    let mut accounts = match &args.opening {
+        Some(path) => read_opening(path, output.precision)?,
+        None => Accounts::default(),
+    };
+    let mut statement = args
+        .statement
+        .map(|client| StatementCollector::new(client, &accounts));
+    let mut disputes = args.disputes.as_ref().map(|_| DisputeTracker::default());
+    let summary = if args.mmap {
+        process_txs_mmap(&args.input.path, &mut accounts, &config)?
+    } else {
+        process_observed(
+            args,
+            &config,
+            &output,
+            &mut accounts,
+            &mut statement,
+            &mut disputes,
+        )?
+    };
+
+    if let (Some(path), Some(disputes)) = (&args.disputes, &disputes) {
+        let report = DisputeReport::new(&accounts, disputes);
+        write_atomic(path, |writer| report.write_csv(writer))
+            .context("Failed to write the disputes report")?;
+    }
+
+    if let Some(statement) = statement {
+        // write the statement instead of accounts
+        let statement = statement.finish(&accounts);
+        let format = args.statement_format.unwrap_or_default();
+        match &args.output {
+            Some(path) => write_atomic(path, |writer| statement.write(writer, format)),
+            None => statement.write(std::io::stdout().lock(), format),
+        }
+        .context("Failed to write the statement")?;
+    } else if args.flush_every.is_none() {
+        // changed accounts have not been flushed yet, write all of them
+        match &args.output {
+            Some(path) => write_atomic(path, |writer| write_accounts(writer, &accounts, &output))
+                .context("Failed to write accounts to the output file")?,
+            None => {
+                print_accounts(&accounts, &output).context("Failed to write accounts to stdout")?
+            }
+        }
+    }
+
+    Ok(summary_code(&summary))
+}
+
+/// Process the input, writing the journal, ledger and changed accounts as the transactions are processed
+fn process_observed(
+    args: &ProcessArgs,
+    config: &ProcessConfig,
+    output: &OutputConfig,
+    accounts: &mut Accounts,
+    statement: &mut Option<StatementCollector>,
+    disputes: &mut Option<DisputeTracker>,
+) -> anyhow::Result<ProcessSummary> {
+    let mut reader = path_reader_with(&args.input.path, &config.input)?;
+    let mut journal = args
+        .journal
+        .as_deref()
+        .map(AtomicFile::create)
+        .transpose()?
+        .map(JournalWriter::new);
+    let mut ledger = args
+        .ledger
+        .as_deref()
+        .map(AtomicFile::create)
+        .transpose()?
+        .map(LedgerWriter::new);
+    if let Some(ledger) = &mut ledger {
+        // opening balances are posted first, so that the ledger matches the accounts
+        ledger.post_opening(accounts)?;
+    }
+    let mut delta = args
+        .flush_every
+        .map(|txs| DeltaWriter::new(std::io::stdout().lock(), output.clone(), txs));
+    let mut progress = args.progress.then(|| {
+        let total_bytes = decoded_size(&args.input.path, config.input.encoding);
+        ProgressReporter::new(std::io::stderr(), total_bytes)
+    });
+    let mut observer = (
+        ((&mut journal, &mut ledger), &mut progress),
+        ((statement, disputes), &mut delta),
+    );
+    let summary = process_txs_with(&mut reader, accounts, config, &mut observer)?;
+    if let Some(delta) = delta {
+        delta.finish(accounts)?;
+    }
+    // the journal and ledger only replace the previous ones once the input is fully processed
+    if let Some(journal) = journal {
+        journal.finish()?.commit()?;
+    }
+    if let Some(ledger) = ledger {
+        ledger.finish()?.commit()?;
+    }
+    Ok(summary)
+}
+
+/// Size of a file after decoding, if it is known without reading the whole file.
+/// Only UTF-8 input keeps its size, minus the BOM that is stripped.
+fn decoded_size(path: &str, encoding: InputEncoding) -> Option<u64> {
+    if encoding != InputEncoding::Utf8 {
+        return None;
+    }
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut head = Vec::with_capacity(3);
+    file.by_ref().take(3).read_to_end(&mut head).ok()?;
+    let size = file.metadata().ok()?.len();
+    match encoding_rs::Encoding::for_bom(&head) {
+        None => Some(size),
+        Some((encoding, bom)) if encoding == encoding_rs::UTF_8 => Some(size - bom as u64),
+        // a BOM of another encoding overrides the configured one
+        Some(_) => None,
+    }
+}
+
+/// Report every structural and referential problem of the input without applying it
+fn validate(args: &InputArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let input = args.config(settings)?;
+    let mut reader = path_reader_with(&args.path, &input)?;
+    let problems = tx_engine::validate::validate(&mut reader, &input.amount)?;
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if problems.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        eprintln!("Error: {} rows failed validation", problems.len());
+        Ok(ExitCode::from(EXIT_REJECTED))
+    }
+}
+
+/// Print a summary of the input without applying it
+fn stats(args: &StatsArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let input = args.input.config(settings)?;
+    let mut reader = path_reader_with(&args.input.path, &input)?;
+    let mut collector = StatsCollector::new(args.largest);
+    for (_, tx) in numbered_record_iter(&mut reader, &input.amount)? {
+        collector.add(&tx);
+    }
+    let stats = collector.finish();
+    stats.write(std::io::stdout().lock(), args.format.unwrap_or_default())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print every transaction of a client with its outcome and balances
+fn explain(args: &ExplainArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let config = ProcessConfig {
+        input: args.input.config(settings)?,
+        rules: settings.rules,
+        ..Default::default()
+    };
+    let mut reader = path_reader_with(&args.input.path, &config.input)?;
     let mut accounts = Accounts::default();
-    process_txs(&mut reader, &mut accounts);
+    let mut explainer = Explainer::new(std::io::stdout().lock(), args.client, &accounts);
+    let summary = process_txs_with(&mut reader, &mut accounts, &config, &mut explainer)?;
+    drop(explainer.finish()?);
+    Ok(summary_code(&summary))
+}
+
+/// Run an interactive session on stdin
+fn repl(args: &ReplArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let mut repl = Repl::new(ProcessConfig {
+        input: settings.input.clone(),
+        rules: settings.rules,
+        ..Default::default()
+    });
+    let mut stdout = std::io::stdout().lock();
+    if let Some(path) = &args.path {
+        repl.execute(&format!("load {path}"), &mut stdout)?;
+    }
+    repl.run(std::io::stdin().lock(), stdout)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Compare two account snapshots
+fn diff(args: &DiffArgs) -> anyhow::Result<ExitCode> {
+    let diff = diff_accounts(&read_snapshot(&args.before)?, &read_snapshot(&args.after)?);
+    diff.write_text(std::io::stdout().lock())?;
+    if diff.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(EXIT_DIFFERENT))
+    }
+}
+
+/// Write a synthetic transaction file
+fn generate(args: &GenerateArgs) -> anyhow::Result<ExitCode> {
+    let config = args.config();
+    config.validate()?;
+    match &args.output {
+        Some(path) => write_atomic(path, |writer| {
+            tx_engine::generate::generate(writer, &config)
+        })
+        .context("Failed to write the generated file")?,
+        None => tx_engine::generate::generate(std::io::stdout().lock(), &config)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let args = std::iter::once("tx_engine").chain(args.iter().copied());
+        Cli::try_parse_from(with_default_command(args.map(OsString::from).collect()))
+    }
+
+    #[test]
+    fn test_config_with_every_command() {
+        let commands: [&[&str]; 8] = [
+            &["input.csv"],
+            &["process", "input.csv"],
+            &["validate", "input.csv"],
+            &["stats", "input.csv"],
+            &["explain", "--client", "1", "input.csv"],
+            &["repl"],
+            &["diff", "before.csv", "after.csv"],
+            &["generate"],
+        ];
+        for command in commands {
+            for config in [
+                &["--config", "settings.toml"][..],
+                &["--config=settings.toml"],
+            ] {
+                for args in [[config, command].concat(), [command, config].concat()] {
+                    let cli = parse(&args).unwrap_or_else(|err| panic!("{args:?}: {err}"));
+                    assert_eq!(cli.config, Some(PathBuf::from("settings.toml")), "{args:?}");
+                }
+            }
+        }
 
-    // Normalize and print accounts to stdout
-    print_accounts(&mut accounts).expect("Failed to write accounts to stdout");
+        assert!(matches!(
+            parse(&["--config", "settings.toml", "input.csv"])
+                .unwrap()
+                .command,
+            Command::Process(_)
+        ));
+        assert!(matches!(
+            parse(&["--config", "settings.toml", "validate", "input.csv"])
+                .unwrap()
+                .command,
+            Command::Validate(_)
+        ));
+        // a missing config path is still a usage error
+        assert!(parse(&["--config"]).is_err());
+    }
 }
@@ -0,0 +1,57 @@
+//! Opening balances loaded before processing, such as the closing balances of the previous run.
+//!
+//! The balances are read from account rows as written by the account output, in CSV, JSON or NDJSON.
+//! Deposits of earlier runs are not part of the rows, so they can no longer be disputed.
+
+use std::path::Path;
+
+use anyhow::Context;
+use rust_decimal::Decimal;
+
+use crate::diff::{Snapshot, read_snapshot};
+use crate::types::{Accounts, ClientAccount};
+
+/// Read the opening balances of a file, written with the given output precision
+pub fn read_opening(path: &Path, precision: u32) -> anyhow::Result<Accounts> {
+    let snapshot = read_snapshot(path)?;
+    opening_accounts(snapshot, precision)
+        .with_context(|| format!("Invalid opening balances in {}", path.display()))
+}
+
+/// Accounts with the balances of a snapshot, checking that every total equals available plus held.
+/// Rows written with the given output precision are rounded, so the total may differ by one unit
+/// of that precision, and the accounts start from the exact sum instead.
+/// All accounts are marked as changed, so that they are part of the first flush.
+pub fn opening_accounts(snapshot: Snapshot, precision: u32) -> anyhow::Result<Accounts> {
+    let unit = Decimal::new(1, precision);
+    let mut accounts = Accounts::default();
+    for (client, row) in snapshot {
+        let total = row
+            .available
+            .checked_add(row.held)
+            .with_context(|| format!("Balances of client {client} overflow"))?;
+        if total
+            .checked_sub(row.total)
+            .is_none_or(|diff| diff.abs() > unit)
+        {
+            anyhow::bail!(
+                "Total {} of client {client} is not available {} plus held {}",
+                row.total,
+                row.available,
+                row.held
+            );
+        }
+        accounts.insert(
+            client,
+            ClientAccount {
+                available: row.available,
+                held: row.held,
+                total,
+                locked: row.locked,
+                ..Default::default()
+            },
+        );
+        accounts.mark_dirty(client);
+    }
+    Ok(accounts)
+}
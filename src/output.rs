@@ -0,0 +1,313 @@
+//! Output formats for account rows.
+
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use csv::WriterBuilder;
+use serde::Serialize;
+use tempfile::NamedTempFile;
+
+use crate::config::OutputConfig;
+use crate::types::{AccountRow, Accounts};
+use crate::{TxEvent, TxObserver, flush_accounts};
+
+/// Format of the account output
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// CSV with headers
+    #[default]
+    Csv,
+    /// A single JSON array of accounts
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(anyhow::anyhow!("Unknown output format: {s}")),
+        }
+    }
+}
+
+/// Writes account rows in an output format.
+/// Decimals are always encoded as strings.
+pub trait AccountWriter {
+    /// Write a single account row
+    fn write_row(&mut self, row: &AccountRow) -> anyhow::Result<()>;
+
+    /// Flush the rows written so far to the underlying writer
+    fn flush(&mut self) -> anyhow::Result<()>;
+
+    /// Complete the output and flush the underlying writer
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+/// Construct an account writer for the configured format and precision
+pub fn account_writer<'w, W: Write + 'w>(
+    output: &OutputConfig,
+    writer: W,
+) -> Box<dyn AccountWriter + 'w> {
+    match output.format {
+        OutputFormat::Csv => Box::new(CsvAccountWriter::new(writer)),
+        OutputFormat::Json => Box::new(JsonAccountWriter::new(writer, output.precision)),
+        OutputFormat::Ndjson => Box::new(NdjsonAccountWriter::new(writer, output.precision)),
+    }
+}
+
+/// CSV output with headers
+pub struct CsvAccountWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> CsvAccountWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: WriterBuilder::new().has_headers(true).from_writer(writer),
+        }
+    }
+}
+
+impl<W: Write> AccountWriter for CsvAccountWriter<W> {
+    fn write_row(&mut self, row: &AccountRow) -> anyhow::Result<()> {
+        self.writer.serialize(row)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// JSON array output, with one account per line.
+/// Every flush completes the array of the rows written since the previous one,
+/// so that a stream of flushes is a sequence of complete arrays.
+pub struct JsonAccountWriter<W: Write> {
+    writer: W,
+    /// Decimal places of the amounts
+    precision: u32,
+    /// Rows of the open array
+    rows: usize,
+    /// Whether any array has been completed
+    completed: bool,
+}
+
+impl<W: Write> JsonAccountWriter<W> {
+    pub fn new(writer: W, precision: u32) -> Self {
+        Self {
+            writer,
+            precision,
+            rows: 0,
+            completed: false,
+        }
+    }
+
+    /// Close the open array, if any rows have been written to it
+    fn end_array(&mut self) -> anyhow::Result<()> {
+        if self.rows > 0 {
+            self.writer.write_all(b"\n]\n")?;
+            self.rows = 0;
+            self.completed = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> AccountWriter for JsonAccountWriter<W> {
+    fn write_row(&mut self, row: &AccountRow) -> anyhow::Result<()> {
+        let separator = if self.rows == 0 { "[\n" } else { ",\n" };
+        self.writer.write_all(separator.as_bytes())?;
+        serde_json::to_writer(&mut self.writer, &JsonRow::new(row, self.precision))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.end_array()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.rows == 0 && !self.completed {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.end_array()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Account row of JSON output, with decimals encoded as strings with a fixed number of decimal places
+#[derive(Serialize)]
+struct JsonRow {
+    client: u16,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl JsonRow {
+    /// Encode a row that is already rounded to the precision, padding its amounts with zeros
+    fn new(row: &AccountRow, precision: u32) -> Self {
+        let precision = precision as usize;
+        Self {
+            client: row.client,
+            available: format!("{:.precision$}", row.available),
+            held: format!("{:.precision$}", row.held),
+            total: format!("{:.precision$}", row.total),
+            locked: row.locked,
+        }
+    }
+}
+
+/// Newline delimited JSON output
+pub struct NdjsonAccountWriter<W: Write> {
+    writer: W,
+    /// Decimal places of the amounts
+    precision: u32,
+}
+
+impl<W: Write> NdjsonAccountWriter<W> {
+    pub fn new(writer: W, precision: u32) -> Self {
+        Self { writer, precision }
+    }
+}
+
+impl<W: Write> AccountWriter for NdjsonAccountWriter<W> {
+    fn write_row(&mut self, row: &AccountRow) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.writer, &JsonRow::new(row, self.precision))?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the accounts changed since the last flush after every given number of transactions
+pub struct DeltaWriter<'w> {
+    writer: Box<dyn AccountWriter + 'w>,
+    output: OutputConfig,
+    every: u64,
+    txs: u64,
+}
+
+impl<'w> DeltaWriter<'w> {
+    /// Flush changed accounts in the configured format after every `every` transactions
+    pub fn new<W: Write + 'w>(writer: W, output: OutputConfig, every: u64) -> Self {
+        Self {
+            writer: account_writer(&output, writer),
+            output,
+            every: every.max(1),
+            txs: 0,
+        }
+    }
+
+    /// Flush the remaining changed accounts and complete the output
+    pub fn finish(mut self, accounts: &mut Accounts) -> anyhow::Result<()> {
+        flush_accounts(&mut *self.writer, accounts, &self.output)?;
+        self.writer.finish()
+    }
+}
+
+impl TxObserver for DeltaWriter<'_> {
+    fn on_tx(&mut self, _event: &TxEvent<'_>, _accounts: &Accounts) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn after_tx(&mut self, accounts: &mut Accounts) -> anyhow::Result<()> {
+        self.txs += 1;
+        if self.txs.is_multiple_of(self.every) {
+            flush_accounts(&mut *self.writer, accounts, &self.output)?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// File that is written to a temporary file in the same directory,
+/// and only appears at its path once committed, so that consumers never observe a partial file.
+/// The temporary file is removed if the file is dropped without committing, including on panics.
+pub struct AtomicFile {
+    path: PathBuf,
+    writer: BufWriter<NamedTempFile>,
+}
+
+impl AtomicFile {
+    /// Create a temporary file next to `path`
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = NamedTempFile::new_in(parent_dir(path)).context(format!(
+            "Failed to create a temporary file for {}",
+            path.display()
+        ))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Flush and sync the file to disk, then rename it to its path
+    pub fn commit(self) -> anyhow::Result<()> {
+        let file = self.writer.into_inner().map_err(|err| err.into_error())?;
+        file.as_file().sync_all()?;
+        file.persist(&self.path).context(format!(
+            "Failed to rename output to {}",
+            self.path.display()
+        ))?;
+
+        // make the rename itself durable
+        #[cfg(unix)]
+        std::fs::File::open(parent_dir(&self.path))?.sync_all()?;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Write a file atomically through an [AtomicFile]
+pub fn write_atomic<F>(path: &Path, write: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> anyhow::Result<()>,
+{
+    let mut file = AtomicFile::create(path)?;
+    write(&mut file)?;
+    file.commit()
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
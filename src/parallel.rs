@@ -0,0 +1,162 @@
+//! Memory-mapped, parallel parsing of large local files.
+//!
+//! The input is split into chunks at newline boundaries and the chunks are parsed in parallel.
+//! Parsed batches are applied in their original order, so the result matches [crate::process_txs].
+//!
+//! Chunking assumes that records never contain quoted newlines, which holds for transaction files.
+
+use anyhow::Context;
+use csv::{ByteRecord, ReaderBuilder};
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::config::{InputEncoding, ProcessConfig};
+use crate::control::ControlSource;
+use crate::parse::{AmountFormat, Columns, is_trailer, parse_record, parse_trailer};
+use crate::types::{Accounts, TxOutcome, TxType};
+use crate::{ProcessSummary, process_tx_with};
+
+/// Default size of a chunk parsed by a single thread
+const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Process all transactions of a file by parsing it in parallel chunks
+pub fn process_txs_mmap(
+    path: &str,
+    accounts: &mut Accounts,
+    config: &ProcessConfig,
+) -> anyhow::Result<ProcessSummary> {
+    process_txs_chunked(path, CHUNK_SIZE, accounts, config)
+}
+
+/// Process a file with an explicit chunk size
+pub(crate) fn process_txs_chunked(
+    path: &str,
+    chunk_size: usize,
+    accounts: &mut Accounts,
+    config: &ProcessConfig,
+) -> anyhow::Result<ProcessSummary> {
+    let input = &config.input;
+    // transcoding would defeat the purpose of mapping the file
+    if input.encoding != InputEncoding::Utf8 {
+        anyhow::bail!("Only UTF-8 input can be memory-mapped");
+    }
+    // batches are applied as they are parsed, so a failing row can not roll back the earlier ones
+    if config.strict {
+        anyhow::bail!("Strict mode is not supported for memory-mapped input");
+    }
+    if !matches!(config.control.source, ControlSource::None) {
+        anyhow::bail!("Control totals are not supported for memory-mapped input");
+    }
+
+    let file = std::fs::File::open(path).context(format!("Failed to open {path}"))?;
+    // SAFETY: the file is only read, and is expected not to be modified while it is processed
+    let mmap = unsafe { Mmap::map(&file) }.context(format!("Failed to map {path}"))?;
+
+    if mmap.starts_with(b"\xff\xfe") || mmap.starts_with(b"\xfe\xff") {
+        anyhow::bail!("UTF-16 input can not be memory-mapped");
+    }
+
+    // a UTF-8 BOM is stripped by the CSV reader of the header
+    let (header, body) = split_header(&mmap);
+    let (columns, width) = parse_header(header)?;
+
+    let mut summary = ProcessSummary::default();
+    let chunks = split_chunks(body, chunk_size);
+    // parse as many chunks as there are threads at a time to bound memory use
+    for window in chunks.chunks(rayon::current_num_threads().max(1)) {
+        // `collect` keeps the batches in the order of the chunks
+        let batches: Vec<Vec<anyhow::Result<TxType>>> = window
+            .par_iter()
+            .map(|chunk| parse_chunk(chunk, &columns, width, &input.amount))
+            .collect();
+
+        for tx_res in batches.into_iter().flatten() {
+            summary.rows += 1;
+            // ignore malformed entries
+            let Ok(tx) = tx_res else {
+                summary.malformed += 1;
+                continue;
+            };
+
+            if let TxType::Deposit(change) | TxType::Withdrawal(change) = &tx {
+                summary.amount = summary.amount.saturating_add(change.amount);
+            }
+            if process_tx_with(tx, accounts, &config.rules) != TxOutcome::Applied {
+                summary.ignored += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Split the input after the first line
+fn split_header(input: &[u8]) -> (&[u8], &[u8]) {
+    match input.iter().position(|&b| b == b'\n') {
+        Some(end) => input.split_at(end + 1),
+        None => (input, &[]),
+    }
+}
+
+/// Resolve the columns and the number of fields from the header line
+fn parse_header(header: &[u8]) -> anyhow::Result<(Columns, usize)> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(header);
+    let headers = reader.byte_headers().context("Failed to read headers")?;
+    Ok((Columns::from_headers(headers)?, headers.len()))
+}
+
+/// Split the input into chunks of roughly `chunk_size` bytes, ending at newlines
+fn split_chunks(mut input: &[u8], chunk_size: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    while input.len() > chunk_size {
+        let end = match input[chunk_size..].iter().position(|&b| b == b'\n') {
+            Some(offset) => chunk_size + offset + 1,
+            None => input.len(),
+        };
+        let (chunk, rest) = input.split_at(end);
+        chunks.push(chunk);
+        input = rest;
+    }
+    if !input.is_empty() {
+        chunks.push(input);
+    }
+    chunks
+}
+
+/// Parse all records of a chunk, with the same reader settings as [crate::path_reader]
+fn parse_chunk(
+    chunk: &[u8],
+    columns: &Columns,
+    width: usize,
+    format: &AmountFormat,
+) -> Vec<anyhow::Result<TxType>> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        // record lengths are checked against the header instead of the first row of the chunk
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(chunk);
+    let mut record = ByteRecord::new();
+    let mut txs = Vec::new();
+    loop {
+        match reader.read_byte_record(&mut record) {
+            Ok(true) if record.len() != width => txs.push(Err(anyhow::anyhow!(
+                "Expected {width} fields, found {}",
+                record.len()
+            ))),
+            Ok(true) if is_trailer(&record, columns) => {
+                // control totals are not checked, but the trailer must still be valid
+                if let Err(err) = parse_trailer(&record, columns, format) {
+                    txs.push(Err(err));
+                }
+            }
+            Ok(true) => txs.push(parse_record(&record, columns, format)),
+            Ok(false) => break,
+            Err(err) => txs.push(Err(err).context("Failed to read the record")),
+        }
+    }
+    txs
+}
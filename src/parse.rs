@@ -0,0 +1,283 @@
+//! Allocation-free parsing of transaction records.
+//!
+//! Unlike [crate::record_iter], which deserializes every row into an owned [crate::types::CsvRow],
+//! this path reuses a single [ByteRecord] and matches the columns directly from bytes.
+
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use anyhow::{Context, bail};
+use csv::{ByteRecord, Reader};
+use rust_decimal::Decimal;
+
+use crate::control::ControlTotal;
+use crate::types::{BalanceChange, Dispute, TxType};
+
+/// Positions of the transaction columns, resolved from the CSV headers
+#[derive(Debug, Clone, Copy)]
+pub struct Columns {
+    tx_type: usize,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+}
+
+impl Columns {
+    /// Resolve column positions by header name, the same way [crate::types::CsvRow] does
+    pub fn from_headers(headers: &ByteRecord) -> anyhow::Result<Self> {
+        let find = |name: &str| headers.iter().position(|h| h == name.as_bytes());
+        Ok(Self {
+            tx_type: find("type").context("Missing `type` column")?,
+            client: find("client").context("Missing `client` column")?,
+            tx: find("tx").context("Missing `tx` column")?,
+            // amount is optional, same as in `CsvRow`
+            amount: find("amount"),
+        })
+    }
+}
+
+/// Longest normalized amount accepted by [AmountFormat::parse]
+const MAX_AMOUNT_LEN: usize = 64;
+
+/// How amounts are written in the input.
+/// All formats normalize into the same [Decimal].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmountFormat {
+    /// Amounts are integers in minor units with this exponent, e.g. `2` for cents
+    pub minor_units: Option<u32>,
+    /// Separator of the fractional part, e.g. `,` for `100,50`
+    pub decimal_separator: char,
+    /// Separator of thousands groups that is ignored, e.g. `.` for `1.000,50`
+    pub thousands_separator: Option<char>,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        Self {
+            minor_units: None,
+            decimal_separator: '.',
+            thousands_separator: None,
+        }
+    }
+}
+
+impl AmountFormat {
+    /// Amount format with the given separators and minor units, checking that amounts are unambiguous
+    pub fn new(
+        minor_units: Option<u32>,
+        decimal_separator: char,
+        thousands_separator: Option<char>,
+    ) -> anyhow::Result<Self> {
+        if let Some(exponent) = minor_units
+            && exponent > Decimal::MAX_SCALE
+        {
+            bail!(
+                "Minor units of {exponent} exceed the maximum of {} decimal places",
+                Decimal::MAX_SCALE
+            );
+        }
+        for separator in [Some(decimal_separator), thousands_separator]
+            .into_iter()
+            .flatten()
+        {
+            if separator.is_ascii_digit() || matches!(separator, '-' | '+') {
+                bail!("Invalid amount separator {separator:?}");
+            }
+        }
+        if thousands_separator == Some(decimal_separator) {
+            bail!("Decimal and thousands separators must differ, both are {decimal_separator:?}");
+        }
+        Ok(Self {
+            minor_units,
+            decimal_separator,
+            thousands_separator,
+        })
+    }
+
+    /// Parse an amount in this format
+    pub fn parse(&self, field: &str) -> anyhow::Result<Decimal> {
+        // plain decimals need no normalization
+        if *self == Self::default() {
+            return parse_decimal(field);
+        }
+
+        // normalize into a stack buffer to avoid allocating for every row
+        let mut buf = [0u8; MAX_AMOUNT_LEN];
+        let mut len = 0;
+        for c in field.chars() {
+            if Some(c) == self.thousands_separator {
+                continue;
+            }
+            let c = if c == self.decimal_separator { '.' } else { c };
+            if !c.is_ascii() || len == buf.len() {
+                bail!("Invalid amount: {field}");
+            }
+            buf[len] = c as u8;
+            len += 1;
+        }
+        let normalized = std::str::from_utf8(&buf[..len])?;
+
+        match self.minor_units {
+            Some(exponent) => {
+                let units: i128 = normalized
+                    .parse()
+                    .with_context(|| format!("Invalid amount in minor units: {field}"))?;
+                Decimal::try_from_i128_with_scale(units, exponent)
+                    .with_context(|| format!("Invalid amount in minor units: {field}"))
+            }
+            None => parse_decimal(normalized),
+        }
+    }
+}
+
+/// Iterator over transactions that reuses a single [ByteRecord] for every row.
+/// Trailer rows are not yielded, but kept aside and available from [ByteRecordIter::trailer].
+pub struct ByteRecordIter<'r, R> {
+    reader: &'r mut Reader<R>,
+    record: ByteRecord,
+    columns: Columns,
+    format: AmountFormat,
+    trailer: Option<ControlTotal>,
+}
+
+impl<'r, R: std::io::Read> ByteRecordIter<'r, R> {
+    /// Read the headers and prepare the iterator
+    pub fn new(reader: &'r mut Reader<R>, format: AmountFormat) -> anyhow::Result<Self> {
+        let headers = reader.byte_headers().context("Failed to read headers")?;
+        let columns = Columns::from_headers(headers)?;
+        Ok(Self {
+            reader,
+            record: ByteRecord::new(),
+            columns,
+            format,
+            trailer: None,
+        })
+    }
+
+    /// Line of the most recently read record
+    pub fn line(&self) -> u64 {
+        self.record.position().map_or(0, |pos| pos.line())
+    }
+
+    /// Bytes of the input read so far, after decoding
+    pub fn bytes(&self) -> u64 {
+        self.reader.position().byte()
+    }
+
+    /// Control totals of the trailer row, if one has been read
+    pub fn trailer(&self) -> Option<&ControlTotal> {
+        self.trailer.as_ref()
+    }
+}
+
+impl<R: std::io::Read> Iterator for ByteRecordIter<'_, R> {
+    type Item = anyhow::Result<TxType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            return match self.reader.read_byte_record(&mut self.record) {
+                Ok(true) if is_trailer(&self.record, &self.columns) => {
+                    match parse_trailer(&self.record, &self.columns, &self.format) {
+                        Ok(trailer) => {
+                            self.trailer = Some(trailer);
+                            continue;
+                        }
+                        Err(err) => Some(Err(err)),
+                    }
+                }
+                Ok(true) => Some(parse_record(&self.record, &self.columns, &self.format)),
+                Ok(false) => None,
+                Err(err) => Some(Err(err).context("Failed to read the record")),
+            };
+        }
+    }
+}
+
+/// Parse a single record into a transaction.
+/// Produces the same [TxType] as `CsvRow` deserialization followed by [TxType::from_row].
+pub fn parse_record(
+    record: &ByteRecord,
+    columns: &Columns,
+    format: &AmountFormat,
+) -> anyhow::Result<TxType> {
+    let tx_type = field(record, columns.tx_type, "type")?;
+    let client = parse_int(
+        field(record, columns.client, "client")?,
+        u16::from_str_radix,
+    )?;
+    let tx = parse_int(field(record, columns.tx, "tx")?, u32::from_str_radix)?;
+    let amount = match columns.amount.and_then(|i| record.get(i)) {
+        // missing and empty amounts are both treated as absent
+        None | Some(b"") => None,
+        Some(amount) => Some(format.parse(std::str::from_utf8(amount)?)?),
+    };
+
+    match tx_type {
+        b"deposit" => Ok(TxType::Deposit(BalanceChange {
+            client,
+            tx,
+            amount: amount.ok_or_else(|| anyhow::anyhow!("Missing amount for deposit"))?,
+        })),
+        b"withdrawal" => Ok(TxType::Withdrawal(BalanceChange {
+            client,
+            tx,
+            amount: amount.ok_or_else(|| anyhow::anyhow!("Missing amount for withdrawal"))?,
+        })),
+        b"dispute" => Ok(TxType::Dispute(Dispute { client, tx })),
+        b"resolve" => Ok(TxType::Resolve(Dispute { client, tx })),
+        b"chargeback" => Ok(TxType::Chargeback(Dispute { client, tx })),
+        _ => Err(anyhow::anyhow!(
+            "Unknown transaction type: {}",
+            String::from_utf8_lossy(tx_type)
+        )),
+    }
+}
+
+/// Whether the record is a trailer row with control totals
+pub fn is_trailer(record: &ByteRecord, columns: &Columns) -> bool {
+    record.get(columns.tx_type) == Some(b"trailer")
+}
+
+/// Parse a trailer row, which holds the record count in `tx` and the sum of amounts in `amount`
+pub fn parse_trailer(
+    record: &ByteRecord,
+    columns: &Columns,
+    format: &AmountFormat,
+) -> anyhow::Result<ControlTotal> {
+    let records = parse_int(field(record, columns.tx, "tx")?, u64::from_str_radix)
+        .context("Invalid trailer record count")?;
+    let amount = columns
+        .amount
+        .and_then(|i| record.get(i))
+        .context("Missing trailer amount")?;
+    Ok(ControlTotal {
+        records,
+        amount: format.parse(std::str::from_utf8(amount)?)?,
+    })
+}
+
+fn field<'a>(record: &'a ByteRecord, index: usize, name: &str) -> anyhow::Result<&'a [u8]> {
+    record
+        .get(index)
+        .with_context(|| format!("Missing field `{name}`"))
+}
+
+/// Parse an integer the way the `csv` deserializer does, including the `0x` hex prefix
+fn parse_int<T>(
+    field: &[u8],
+    from_str_radix: fn(&str, u32) -> Result<T, ParseIntError>,
+) -> anyhow::Result<T> {
+    let field = std::str::from_utf8(field)?;
+    let num = match field.strip_prefix("0x") {
+        Some(digits) => from_str_radix(digits, 16),
+        None => from_str_radix(field, 10),
+    };
+    Ok(num?)
+}
+
+/// Parse a decimal the way `rust_decimal`'s serde visitor does
+fn parse_decimal(field: &str) -> anyhow::Result<Decimal> {
+    Decimal::from_str(field)
+        .or_else(|_| Decimal::from_scientific(field))
+        .with_context(|| format!("Invalid amount: {field}"))
+}
@@ -0,0 +1,174 @@
+//! Progress of long-running inputs.
+//!
+//! [crate::process_txs_with] reports the progress to its [TxObserver] every [PROGRESS_ROWS] rows.
+//! [ProgressReporter] writes it as throttled status lines, [ProgressFn] passes it to a callback.
+
+use std::io::Write;
+use std::time::Duration;
+
+use crate::types::Accounts;
+use crate::{ProcessSummary, TxEvent, TxObserver};
+
+/// Rows processed between two progress reports
+pub const PROGRESS_ROWS: u64 = 8192;
+
+/// Minimum time between two lines of a [ProgressReporter]
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Progress of processing an input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// Number of rows read so far
+    pub rows: u64,
+    /// Number of rows that failed to parse
+    pub malformed: u64,
+    /// Number of transactions ignored by the engine
+    pub ignored: u64,
+    /// Bytes of the input read so far, after decoding.
+    /// They only add up to the file size for UTF-8 input.
+    pub bytes: u64,
+    /// Time since processing started
+    pub elapsed: Duration,
+    /// Whether the whole input has been read
+    pub done: bool,
+}
+
+impl Progress {
+    pub fn new(summary: &ProcessSummary, bytes: u64, elapsed: Duration, done: bool) -> Self {
+        Self {
+            rows: summary.rows,
+            malformed: summary.malformed,
+            ignored: summary.ignored,
+            bytes,
+            elapsed,
+            done,
+        }
+    }
+
+    /// Number of rows rejected as malformed or ignored
+    pub fn rejected(&self) -> u64 {
+        self.malformed + self.ignored
+    }
+
+    /// Average throughput since processing started
+    pub fn rows_per_sec(&self) -> f64 {
+        rate(self.rows, self.elapsed)
+    }
+
+    /// Estimated time until an input of the given size is read, at the average throughput so far
+    pub fn eta(&self, total_bytes: u64) -> Option<Duration> {
+        let bytes_per_sec = rate(self.bytes, self.elapsed);
+        if bytes_per_sec <= 0.0 {
+            return None;
+        }
+        let remaining = total_bytes.saturating_sub(self.bytes) as f64;
+        Some(Duration::from_secs_f64(remaining / bytes_per_sec))
+    }
+}
+
+fn rate(count: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        0.0 => 0.0,
+        secs => count as f64 / secs,
+    }
+}
+
+/// Passes the progress to a callback
+pub struct ProgressFn<F>(pub F);
+
+impl<F: FnMut(&Progress)> TxObserver for ProgressFn<F> {
+    fn on_tx(&mut self, _event: &TxEvent<'_>, _accounts: &Accounts) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn on_progress(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        (self.0)(progress);
+        Ok(())
+    }
+}
+
+/// Writes the progress as a status line at most every [REPORT_INTERVAL], and once at the end
+pub struct ProgressReporter<W: Write> {
+    writer: W,
+    /// Size of the input after decoding, to report the share read and an ETA
+    total_bytes: Option<u64>,
+    /// Elapsed time of the last line written
+    last: Duration,
+}
+
+impl<W: Write> ProgressReporter<W> {
+    pub fn new(writer: W, total_bytes: Option<u64>) -> Self {
+        Self {
+            writer,
+            total_bytes,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Status line of the progress
+    fn line(&self, progress: &Progress) -> String {
+        let mut line = format!(
+            "{} rows, {:.0} rows/s, {} rejected ({} malformed, {} ignored), {}",
+            progress.rows,
+            progress.rows_per_sec(),
+            progress.rejected(),
+            progress.malformed,
+            progress.ignored,
+            format_bytes(progress.bytes),
+        );
+        if let Some(total) = self.total_bytes {
+            let share = match total {
+                0 => 100.0,
+                total => progress.bytes as f64 * 100.0 / total as f64,
+            };
+            line += &format!(" of {} ({share:.1}%)", format_bytes(total));
+        }
+        if progress.done {
+            line += &format!(", done in {}", format_duration(progress.elapsed));
+        } else if let Some(eta) = self.total_bytes.and_then(|total| progress.eta(total)) {
+            line += &format!(", ETA {}", format_duration(eta));
+        }
+        line
+    }
+}
+
+impl<W: Write> TxObserver for ProgressReporter<W> {
+    fn on_tx(&mut self, _event: &TxEvent<'_>, _accounts: &Accounts) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn on_progress(&mut self, progress: &Progress) -> anyhow::Result<()> {
+        if !progress.done && progress.elapsed < self.last + REPORT_INTERVAL {
+            return Ok(());
+        }
+        self.last = progress.elapsed;
+        writeln!(self.writer, "{}", self.line(progress))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Format a number of bytes in binary units, e.g. `1.5 GiB`
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        unit => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// Format a duration in whole seconds, e.g. `1h 02m 03s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, s) => format!("{h}h {m:02}m {s:02}s"),
+    }
+}
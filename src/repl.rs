@@ -0,0 +1,173 @@
+//! Interactive shell for exploring and changing the state of the engine.
+//!
+//! Typed transactions are parsed with the same [CsvRow] conversion as input files,
+//! and applied with [process_tx_with], so the shell behaves exactly like a file run.
+
+use std::io::{BufRead, Write};
+
+use anyhow::Context;
+use csv::StringRecord;
+
+use crate::config::{OutputConfig, ProcessConfig};
+use crate::disputes::{DisputeReport, DisputeTracker};
+use crate::types::{Accounts, Balances, CsvRow, TxType};
+use crate::{path_reader_with, process_tx_with, process_txs_with, write_accounts};
+
+const HELP: &str = "\
+Commands:
+  deposit <client> <tx> <amount>     Deposit funds
+  withdrawal <client> <tx> <amount>  Withdraw funds
+  dispute <client> <tx>              Dispute a deposit
+  resolve <client> <tx>              Resolve a dispute
+  chargeback <client> <tx>           Charge back a dispute
+  load <path>                        Process a CSV file
+  accounts                           Print all accounts
+  account <client>                   Print the balances of a client
+  disputes                           List the open disputes
+  undo                               Undo the last transaction or load
+  help                               Print this help
+  quit                               End the session";
+
+/// Interactive session over a set of accounts
+#[derive(Debug, Default)]
+pub struct Repl {
+    accounts: Accounts,
+    /// Accounts before every command that changed them, for undo
+    history: Vec<Accounts>,
+    config: ProcessConfig,
+}
+
+impl Repl {
+    /// Start a session with empty accounts, loading files with the given settings
+    pub fn new(config: ProcessConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    /// Read commands until the input ends or the session is quit.
+    /// Failed commands are reported and leave the accounts unchanged.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> anyhow::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                writeln!(output)?;
+                return Ok(());
+            };
+            match self.execute(&line, &mut output) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(err) => writeln!(output, "Error: {err:#}")?,
+            }
+        }
+    }
+
+    /// Execute a single command, returning false once the session should end
+    pub fn execute<W: Write>(&mut self, command: &str, output: &mut W) -> anyhow::Result<bool> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit" | "exit"] => return Ok(false),
+            ["help"] => writeln!(output, "{HELP}")?,
+            ["load", path] => self.load(path, output)?,
+            ["accounts"] => write_accounts(&mut *output, &self.accounts, &OutputConfig::default())?,
+            ["account", client] => {
+                let client: u16 = client.parse().context("Invalid client")?;
+                match self.accounts.get(&client) {
+                    Some(account) => writeln!(output, "{}", Balances::from(account))?,
+                    None => writeln!(output, "No account for client {client}")?,
+                }
+            }
+            ["disputes"] => {
+                let report = DisputeReport::new(&self.accounts, &DisputeTracker::default());
+                for dispute in &report.disputes {
+                    writeln!(
+                        output,
+                        "client {} tx {}: {}",
+                        dispute.client,
+                        dispute.tx,
+                        dispute.amount.round_dp(4)
+                    )?;
+                }
+                if report.disputes.is_empty() {
+                    writeln!(output, "No open disputes")?;
+                }
+            }
+            ["undo"] => match self.history.pop() {
+                Some(accounts) => {
+                    self.accounts = accounts;
+                    writeln!(output, "Undone")?;
+                }
+                None => writeln!(output, "Nothing to undo")?,
+            },
+            [name, ..]
+                if matches!(
+                    *name,
+                    "deposit" | "withdrawal" | "dispute" | "resolve" | "chargeback"
+                ) =>
+            {
+                let tx = self.parse_tx(&words)?;
+                let client = tx.client();
+                self.history.push(self.accounts.clone());
+                let outcome = process_tx_with(tx, &mut self.accounts, &self.config.rules);
+                writeln!(
+                    output,
+                    "{outcome}, {}",
+                    Balances::of(&self.accounts, client)
+                )?;
+            }
+            [name, ..] => anyhow::bail!("Unknown command {name}, type help for the commands"),
+        }
+        Ok(true)
+    }
+
+    /// Parse a typed transaction the same way as a row of an input file
+    fn parse_tx(&self, words: &[&str]) -> anyhow::Result<TxType> {
+        let headers = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        if words.len() > headers.len() {
+            anyhow::bail!("Too many arguments, type help for the commands");
+        }
+        let mut record = StringRecord::from(words.to_vec());
+        // the amount of disputes is empty, as in input files
+        while record.len() < headers.len() {
+            record.push_field("");
+        }
+        let row: CsvRow = record
+            .deserialize(Some(&headers))
+            .context("Invalid transaction")?;
+        TxType::from_row(row, &self.config.input.amount)
+    }
+
+    /// Process a file, restoring the accounts if it fails
+    fn load<W: Write>(&mut self, path: &str, output: &mut W) -> anyhow::Result<()> {
+        let before = self.accounts.clone();
+        let result = path_reader_with(path, &self.config.input).and_then(|mut reader| {
+            process_txs_with(&mut reader, &mut self.accounts, &self.config, &mut ())
+        });
+        match result {
+            Ok(summary) => {
+                self.history.push(before);
+                writeln!(
+                    output,
+                    "Loaded {} rows, {} malformed",
+                    summary.rows, summary.malformed
+                )?;
+                if let Some(warning) = &summary.control_warning {
+                    writeln!(output, "Warning: {warning}")?;
+                }
+                Ok(())
+            }
+            Err(err) => {
+                self.accounts = before;
+                Err(err)
+            }
+        }
+    }
+}
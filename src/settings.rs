@@ -0,0 +1,169 @@
+//! Engine settings loaded from a TOML file.
+//!
+//! Every key is optional and defaults to the built-in setting:
+//!
+//! ```toml
+//! [input]
+//! encoding = "latin1"
+//! minor_units = 2
+//! decimal_separator = ","
+//! thousands_separator = "."
+//!
+//! [output]
+//! format = "json"
+//! order = "total"
+//! precision = 2
+//! rounding = "half-up"
+//!
+//! [rules]
+//! lock_on_chargeback = true
+//! dispute_requires_funds = false
+//! ```
+
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+
+use crate::config::{AccountOrder, InputConfig, InputEncoding, OutputConfig, Rounding, Rules};
+use crate::output::OutputFormat;
+use crate::parse::AmountFormat;
+
+/// Settings of a config file
+#[derive(Debug, Default, Clone)]
+pub struct Settings {
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub rules: Rules,
+}
+
+impl Settings {
+    /// Load the settings of a TOML file
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        contents
+            .parse()
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+impl FromStr for Settings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: SettingsFile = toml::from_str(s)?;
+        Ok(Self {
+            input: file.input.config()?,
+            output: file.output.config()?,
+            rules: file.rules,
+        })
+    }
+}
+
+/// Contents of a config file, before validation
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsFile {
+    input: InputSection,
+    output: OutputSection,
+    rules: Rules,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct InputSection {
+    #[serde(deserialize_with = "parse")]
+    encoding: InputEncoding,
+    minor_units: Option<u32>,
+    decimal_separator: char,
+    thousands_separator: Option<char>,
+}
+
+impl Default for InputSection {
+    fn default() -> Self {
+        let amount = AmountFormat::default();
+        Self {
+            encoding: InputEncoding::default(),
+            minor_units: amount.minor_units,
+            decimal_separator: amount.decimal_separator,
+            thousands_separator: amount.thousands_separator,
+        }
+    }
+}
+
+impl InputSection {
+    fn config(self) -> anyhow::Result<InputConfig> {
+        let amount = AmountFormat::new(
+            self.minor_units,
+            self.decimal_separator,
+            self.thousands_separator,
+        )
+        .context("Invalid [input] amount format")?;
+        Ok(InputConfig {
+            encoding: self.encoding,
+            amount,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OutputSection {
+    #[serde(deserialize_with = "parse")]
+    format: OutputFormat,
+    #[serde(deserialize_with = "parse")]
+    order: AccountOrder,
+    precision: u32,
+    #[serde(deserialize_with = "parse")]
+    rounding: Rounding,
+}
+
+impl Default for OutputSection {
+    fn default() -> Self {
+        let output = OutputConfig::default();
+        Self {
+            format: output.format,
+            order: output.order,
+            precision: output.precision,
+            rounding: output.rounding,
+        }
+    }
+}
+
+impl OutputSection {
+    fn config(self) -> anyhow::Result<OutputConfig> {
+        check_scale("output.precision", self.precision)?;
+        Ok(OutputConfig {
+            format: self.format,
+            order: self.order,
+            precision: self.precision,
+            rounding: self.rounding,
+        })
+    }
+}
+
+/// Check that a number of decimal places is supported by [Decimal]
+fn check_scale(key: &str, scale: u32) -> anyhow::Result<()> {
+    if scale > Decimal::MAX_SCALE {
+        anyhow::bail!(
+            "{key} of {scale} exceeds the maximum of {} decimal places",
+            Decimal::MAX_SCALE
+        );
+    }
+    Ok(())
+}
+
+/// Deserialize a string with the [FromStr] implementation used for command line flags
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,269 @@
+//! Account statements for a single client.
+//!
+//! A statement lists every transaction of the client with its outcome and running balances,
+//! the disputes raised on the client's deposits and their current status,
+//! and the opening and closing balances.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::str::FromStr;
+
+use csv::WriterBuilder;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::types::{Accounts, Balances, DisputeState, TxOutcome, TxType};
+use crate::{TxEvent, TxObserver};
+
+/// Format of a statement
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum StatementFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// CSV with opening and closing rows around the transactions
+    Csv,
+}
+
+impl FromStr for StatementFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(StatementFormat::Text),
+            "csv" => Ok(StatementFormat::Csv),
+            _ => Err(anyhow::anyhow!("Unknown statement format: {s}")),
+        }
+    }
+}
+
+/// Transaction of the client, with the balances after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementEntry {
+    pub line: u64,
+    pub tx: u32,
+    pub tx_type: &'static str,
+    /// Amount of the transaction, or of the deposit referred to by a dispute
+    pub amount: Option<Decimal>,
+    pub outcome: TxOutcome,
+    pub balances: Balances,
+}
+
+/// Dispute raised on a deposit of the client
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementDispute {
+    pub tx: u32,
+    pub amount: Decimal,
+    /// Current state of the deposit, [DisputeState::None] once the dispute is resolved
+    pub state: DisputeState,
+}
+
+impl StatementDispute {
+    /// Human readable status of the dispute
+    pub fn status(&self) -> &'static str {
+        match self.state {
+            DisputeState::None => "resolved",
+            DisputeState::Disputed => "open",
+            DisputeState::Chargeback => "charged back",
+        }
+    }
+}
+
+/// Statement of a single client
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub client: u16,
+    pub opening: Balances,
+    pub entries: Vec<StatementEntry>,
+    pub disputes: Vec<StatementDispute>,
+    pub closing: Balances,
+}
+
+/// Collects the transactions of a client while the input is processed
+#[derive(Debug)]
+pub struct StatementCollector {
+    client: u16,
+    opening: Balances,
+    entries: Vec<StatementEntry>,
+    /// Deposits that have been disputed at least once
+    disputed: BTreeSet<u32>,
+}
+
+impl StatementCollector {
+    /// Start a statement from the current state of the accounts
+    pub fn new(client: u16, accounts: &Accounts) -> Self {
+        Self {
+            client,
+            opening: Balances::of(accounts, client),
+            entries: Vec::new(),
+            disputed: BTreeSet::new(),
+        }
+    }
+
+    /// Complete the statement with the recorded deposit history of the client
+    pub fn finish(self, accounts: &Accounts) -> Statement {
+        let deposits = accounts
+            .get(&self.client)
+            .map(|account| &account.deposit_txs);
+        let disputes = self
+            .disputed
+            .iter()
+            .filter_map(|tx| {
+                let deposit = deposits?.get(tx)?;
+                Some(StatementDispute {
+                    tx: *tx,
+                    amount: deposit.amount,
+                    state: deposit.dispute.clone(),
+                })
+            })
+            .collect();
+
+        Statement {
+            client: self.client,
+            opening: self.opening,
+            entries: self.entries,
+            disputes,
+            closing: Balances::of(accounts, self.client),
+        }
+    }
+}
+
+impl TxObserver for StatementCollector {
+    fn on_tx(&mut self, event: &TxEvent<'_>, accounts: &Accounts) -> anyhow::Result<()> {
+        if event.tx.client() != self.client {
+            return Ok(());
+        }
+
+        let amount = event.amount(accounts);
+        if matches!(event.tx, TxType::Dispute(_)) && event.outcome == TxOutcome::Applied {
+            self.disputed.insert(event.tx.tx());
+        }
+
+        self.entries.push(StatementEntry {
+            line: event.line,
+            tx: event.tx.tx(),
+            tx_type: event.tx.name(),
+            amount,
+            outcome: event.outcome,
+            balances: Balances::of(accounts, self.client),
+        });
+        Ok(())
+    }
+}
+
+/// Statement row for csv output
+#[derive(Debug, Serialize)]
+struct StatementRow {
+    line: Option<u64>,
+    tx: Option<u32>,
+    /// Transaction type, or `opening` and `closing` for the balance rows
+    #[serde(rename = "type")]
+    tx_type: &'static str,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    amount: Option<Decimal>,
+    outcome: String,
+    #[serde(with = "rust_decimal::serde::str")]
+    available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    total: Decimal,
+    locked: bool,
+}
+
+impl StatementRow {
+    fn balance(tx_type: &'static str, balances: &Balances) -> Self {
+        Self {
+            line: None,
+            tx: None,
+            tx_type,
+            amount: None,
+            outcome: String::new(),
+            available: balances.available.round_dp(4),
+            held: balances.held.round_dp(4),
+            total: balances.total.round_dp(4),
+            locked: balances.locked,
+        }
+    }
+}
+
+impl Statement {
+    /// Write the statement in the given format
+    pub fn write<W: Write>(&self, writer: W, format: StatementFormat) -> anyhow::Result<()> {
+        match format {
+            StatementFormat::Text => self.write_text(writer),
+            StatementFormat::Csv => self.write_csv(writer),
+        }
+    }
+
+    /// Write the statement as CSV, with opening and closing balance rows
+    pub fn write_csv<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+        let mut writer = WriterBuilder::new().has_headers(true).from_writer(writer);
+        writer.serialize(StatementRow::balance("opening", &self.opening))?;
+        for entry in &self.entries {
+            let mut row = StatementRow::balance(entry.tx_type, &entry.balances);
+            row.line = Some(entry.line);
+            row.tx = Some(entry.tx);
+            row.amount = entry.amount.map(|amount| amount.round_dp(4));
+            row.outcome = entry.outcome.to_string();
+            writer.serialize(row)?;
+        }
+        writer.serialize(StatementRow::balance("closing", &self.closing))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the statement as human readable text
+    pub fn write_text<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        writeln!(writer, "Statement for client {}", self.client)?;
+        writeln!(writer)?;
+        writeln!(writer, "Opening balance: {}", self.opening)?;
+        writeln!(writer)?;
+
+        writeln!(
+            writer,
+            "{:>6}  {:>10}  {:<10}  {:>14}  {:>14}  {:>14}  {:>14}  Outcome",
+            "Line", "Tx", "Type", "Amount", "Available", "Held", "Total"
+        )?;
+        for entry in &self.entries {
+            let amount = entry
+                .amount
+                .map(|amount| amount.round_dp(4).to_string())
+                .unwrap_or_default();
+            writeln!(
+                writer,
+                "{:>6}  {:>10}  {:<10}  {:>14}  {:>14}  {:>14}  {:>14}  {}",
+                entry.line,
+                entry.tx,
+                entry.tx_type,
+                amount,
+                entry.balances.available.round_dp(4).to_string(),
+                entry.balances.held.round_dp(4).to_string(),
+                entry.balances.total.round_dp(4).to_string(),
+                entry.outcome
+            )?;
+        }
+        if self.entries.is_empty() {
+            writeln!(writer, "No transactions")?;
+        }
+        writeln!(writer)?;
+
+        if !self.disputes.is_empty() {
+            writeln!(writer, "Disputes:")?;
+            for dispute in &self.disputes {
+                writeln!(
+                    writer,
+                    "  tx {}: {} ({})",
+                    dispute.tx,
+                    dispute.amount.round_dp(4),
+                    dispute.status()
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        writeln!(writer, "Closing balance: {}", self.closing)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
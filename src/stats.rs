@@ -0,0 +1,223 @@
+//! Statistics of a transaction file, collected without applying the transactions.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use csv::Reader;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::record_iter;
+use crate::types::TxType;
+
+/// Number of largest transactions kept by default
+pub const LARGEST_TXS: usize = 5;
+
+/// Format of the statistics
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(anyhow::anyhow!("Unknown stats format: {s}")),
+        }
+    }
+}
+
+/// Number of rows of each transaction type
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct TxCounts {
+    pub deposit: u64,
+    pub withdrawal: u64,
+    pub dispute: u64,
+    pub resolve: u64,
+    pub chargeback: u64,
+}
+
+/// Deposit or withdrawal among the largest of the input
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LargeTx {
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub tx_type: &'static str,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub amount: Decimal,
+}
+
+/// Statistics of a transaction file
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    /// Number of rows, excluding the header
+    pub rows: u64,
+    /// Number of rows that failed to parse
+    pub malformed: u64,
+    pub counts: TxCounts,
+    /// Number of distinct clients
+    pub clients: usize,
+    /// Sum of all deposits
+    #[serde(with = "rust_decimal::serde::str")]
+    pub deposited: Decimal,
+    /// Sum of all withdrawals
+    #[serde(with = "rust_decimal::serde::str")]
+    pub withdrawn: Decimal,
+    /// Disputes per deposit
+    pub dispute_rate: f64,
+    /// Chargebacks per deposit
+    pub chargeback_rate: f64,
+    /// Largest deposits and withdrawals, largest first
+    pub largest: Vec<LargeTx>,
+}
+
+impl Stats {
+    /// Write the statistics in the given format
+    pub fn write<W: Write>(&self, writer: W, format: StatsFormat) -> anyhow::Result<()> {
+        match format {
+            StatsFormat::Text => self.write_text(writer),
+            StatsFormat::Json => self.write_json(writer),
+        }
+    }
+
+    /// Write the statistics as a JSON object
+    pub fn write_json<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the statistics as human readable text
+    pub fn write_text<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        writeln!(writer, "Rows:            {}", self.rows)?;
+        writeln!(writer, "Malformed:       {}", self.malformed)?;
+        writeln!(writer, "Deposits:        {}", self.counts.deposit)?;
+        writeln!(writer, "Withdrawals:     {}", self.counts.withdrawal)?;
+        writeln!(writer, "Disputes:        {}", self.counts.dispute)?;
+        writeln!(writer, "Resolves:        {}", self.counts.resolve)?;
+        writeln!(writer, "Chargebacks:     {}", self.counts.chargeback)?;
+        writeln!(writer, "Clients:         {}", self.clients)?;
+        writeln!(writer, "Deposited:       {}", self.deposited)?;
+        writeln!(writer, "Withdrawn:       {}", self.withdrawn)?;
+        writeln!(writer, "Dispute rate:    {:.2}%", self.dispute_rate * 100.0)?;
+        writeln!(
+            writer,
+            "Chargeback rate: {:.2}%",
+            self.chargeback_rate * 100.0
+        )?;
+
+        if !self.largest.is_empty() {
+            writeln!(writer)?;
+            writeln!(writer, "Largest transactions:")?;
+            for tx in &self.largest {
+                writeln!(
+                    writer,
+                    "  tx {} of client {}: {} {}",
+                    tx.tx, tx.client, tx.tx_type, tx.amount
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Collects statistics of parsed transactions
+#[derive(Debug)]
+pub struct StatsCollector {
+    stats: Stats,
+    clients: HashSet<u16>,
+    /// Number of largest transactions to keep
+    limit: usize,
+}
+
+impl Default for StatsCollector {
+    fn default() -> Self {
+        Self::new(LARGEST_TXS)
+    }
+}
+
+impl StatsCollector {
+    /// Collect statistics, keeping the given number of largest transactions
+    pub fn new(limit: usize) -> Self {
+        Self {
+            stats: Stats::default(),
+            clients: HashSet::new(),
+            limit,
+        }
+    }
+
+    /// Add a parsed row, counting it as malformed if it failed to parse
+    pub fn add(&mut self, tx: &anyhow::Result<TxType>) {
+        self.stats.rows += 1;
+        let Ok(tx) = tx else {
+            self.stats.malformed += 1;
+            return;
+        };
+
+        self.clients.insert(tx.client());
+        let counts = &mut self.stats.counts;
+        match tx {
+            TxType::Deposit(change) => {
+                counts.deposit += 1;
+                self.stats.deposited = self.stats.deposited.saturating_add(change.amount);
+            }
+            TxType::Withdrawal(change) => {
+                counts.withdrawal += 1;
+                self.stats.withdrawn = self.stats.withdrawn.saturating_add(change.amount);
+            }
+            TxType::Dispute(_) => counts.dispute += 1,
+            TxType::Resolve(_) => counts.resolve += 1,
+            TxType::Chargeback(_) => counts.chargeback += 1,
+        }
+
+        if let TxType::Deposit(change) | TxType::Withdrawal(change) = tx {
+            // earlier transactions come first among equal amounts
+            let largest = &mut self.stats.largest;
+            let index = largest.partition_point(|large| large.amount >= change.amount);
+            if index < self.limit {
+                largest.insert(
+                    index,
+                    LargeTx {
+                        tx: change.tx,
+                        client: change.client,
+                        tx_type: tx.name(),
+                        amount: change.amount,
+                    },
+                );
+                largest.truncate(self.limit);
+            }
+        }
+    }
+
+    /// Complete the statistics
+    pub fn finish(mut self) -> Stats {
+        self.stats.clients = self.clients.len();
+        let deposits = self.stats.counts.deposit;
+        if deposits > 0 {
+            self.stats.dispute_rate = self.stats.counts.dispute as f64 / deposits as f64;
+            self.stats.chargeback_rate = self.stats.counts.chargeback as f64 / deposits as f64;
+        }
+        self.stats
+    }
+}
+
+/// Collect the statistics of all transactions of a reader
+pub fn collect_stats<R: Read>(reader: &mut Reader<R>) -> Stats {
+    let mut collector = StatsCollector::default();
+    for tx in record_iter(reader) {
+        collector.add(&tx);
+    }
+    collector.finish()
+}
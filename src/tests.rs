@@ -1,5 +1,6 @@
 use super::*;
 use crate::types::{ClientAccount, Deposit};
+use csv::WriterBuilder;
 use rust_decimal::dec;
 use std::collections::HashMap;
 
@@ -766,3 +767,1568 @@ fn test_csv_output_precision_4dp() {
         }
     }
 }
+
+/// Parse the same input with both the serde and the byte record paths
+fn parse_both(input: &str) -> (Vec<anyhow::Result<TxType>>, Vec<anyhow::Result<TxType>>) {
+    let builder = || {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .flexible(true);
+        builder
+    };
+    let mut reader = builder().from_reader(input.as_bytes());
+    let serde_txs = record_iter(&mut reader).collect();
+    let mut reader = builder().from_reader(input.as_bytes());
+    let byte_txs = byte_record_iter(&mut reader).unwrap().collect();
+    (serde_txs, byte_txs)
+}
+
+#[test]
+fn test_byte_record_iter_matches_record_iter() {
+    for path in ["all_types.csv", "simple_test.csv"] {
+        let mut reader = path_reader(path).unwrap();
+        let expected: Vec<TxType> = record_iter(&mut reader).map(Result::unwrap).collect();
+        let mut reader = path_reader(path).unwrap();
+        let actual: Vec<TxType> = byte_record_iter(&mut reader)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(actual, expected, "Mismatch in {path}");
+    }
+}
+
+#[test]
+fn test_byte_record_iter_edge_cases() {
+    let input = "\
+amount, tx, type, client
+5, 1, deposit, 0x1f
+1e2, 2, withdrawal, 2
+, 3, dispute, 2
+, 4, deposit, 2
+7, 5, unknown, 2
+7, 6, deposit, -1
+7, 7, deposit
+0.12345, 8, deposit, 3
+";
+    let (serde_txs, byte_txs) = parse_both(input);
+    assert_eq!(serde_txs.len(), byte_txs.len());
+    for (expected, actual) in serde_txs.into_iter().zip(byte_txs) {
+        match (expected, actual) {
+            (Ok(expected), Ok(actual)) => assert_eq!(actual, expected),
+            (Err(_), Err(_)) => {}
+            (expected, actual) => panic!("Mismatch: {expected:?} vs {actual:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_mmap_chunks_match_sequential() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 0..500u32 {
+        let client = tx % 7;
+        let row = match tx % 6 {
+            0..=2 => format!("deposit, {client}, {tx}, {}.25\n", tx % 50),
+            3 => format!("withdrawal, {client}, {tx}, 3.5\n"),
+            // malformed row, must be skipped by both paths
+            4 => format!("deposit, {client}, {tx}\n"),
+            // dispute every other deposit, and charge back the earlier dispute
+            _ if tx % 12 == 5 => format!("dispute, {}, {}, \n", (tx - 5) % 7, tx - 5),
+            _ => format!("chargeback, {}, {}, \n", (tx - 11) % 7, tx - 11),
+        };
+        input.push_str(&row);
+    }
+    std::fs::write(file.path(), input).unwrap();
+    let path = file.path().to_str().unwrap();
+
+    let mut expected = Accounts::default();
+    process_txs(&mut path_reader(path).unwrap(), &mut expected);
+    assert!(expected.values().any(|account| account.locked));
+
+    // small chunks, so that the input is split into many batches
+    for chunk_size in [1, 64, 1000, usize::MAX] {
+        let mut accounts = Accounts::default();
+        parallel::process_txs_chunked(path, chunk_size, &mut accounts, &Default::default())
+            .unwrap();
+        assert_eq!(accounts, expected, "Mismatch with chunk size {chunk_size}");
+    }
+
+    // input without the expected headers fails like the sequential reader
+    let bad = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(bad.path(), "kind, client, tx, amount\ndeposit, 1, 1, 1.0\n").unwrap();
+    let bad_path = bad.path().to_str().unwrap();
+    let sequential = process_txs_with(
+        &mut path_reader(bad_path).unwrap(),
+        &mut Accounts::default(),
+        &Default::default(),
+        &mut (),
+    )
+    .unwrap_err();
+    let mmap = parallel::process_txs_mmap(bad_path, &mut Accounts::default(), &Default::default())
+        .unwrap_err();
+    assert_eq!(format!("{mmap:#}"), format!("{sequential:#}"));
+
+    // settings that need the sequential reader are rejected
+    let strict = config::ProcessConfig {
+        strict: true,
+        ..Default::default()
+    };
+    let control = config::ProcessConfig {
+        control: control::ControlConfig {
+            source: control::ControlSource::Trailer,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    for config in [strict, control] {
+        let mut accounts = Accounts::default();
+        assert!(parallel::process_txs_mmap(path, &mut accounts, &config).is_err());
+        assert!(accounts.is_empty());
+    }
+}
+
+/// Process an in-memory CSV input with the given settings
+fn process_str(
+    input: &str,
+    accounts: &mut Accounts,
+    config: &config::ProcessConfig,
+) -> anyhow::Result<ProcessSummary> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    process_txs_with(&mut reader, accounts, config, &mut ())
+}
+
+/// Process an in-memory CSV input with the default settings, reporting to the observer
+fn process_str_observed(
+    input: &str,
+    accounts: &mut Accounts,
+    observer: &mut dyn TxObserver,
+) -> anyhow::Result<ProcessSummary> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    process_txs_with(&mut reader, accounts, &Default::default(), observer)
+}
+
+#[test]
+fn test_control_total_trailer() {
+    use control::{ControlSource, MismatchAction};
+
+    let mut config = config::ProcessConfig::default();
+    config.control.source = ControlSource::Trailer;
+
+    let complete = "\
+type, client, tx, amount
+deposit, 1, 1, 10.5
+withdrawal, 1, 2, 5
+dispute, 1, 1,
+trailer, , 3, 15.5
+";
+    let mut accounts = Accounts::default();
+    let summary = process_str(complete, &mut accounts, &config).unwrap();
+    assert_eq!(summary.rows, 3);
+    assert_eq!(summary.amount, dec!(15.5));
+
+    // truncated upload, last row is missing
+    let truncated = "\
+type, client, tx, amount
+deposit, 1, 1, 10.5
+withdrawal, 1, 2, 5
+trailer, , 3, 15.5
+";
+    let mut accounts = Accounts::default();
+    let err = process_str(truncated, &mut accounts, &config).unwrap_err();
+    assert!(err.to_string().contains("mismatch"), "{err}");
+
+    // trailer itself is missing
+    let mut accounts = Accounts::default();
+    let err = process_str("type, client, tx, amount\n", &mut accounts, &config).unwrap_err();
+    assert!(err.to_string().contains("Missing trailer"), "{err}");
+
+    // warnings do not fail processing
+    config.control.on_mismatch = MismatchAction::Warn;
+    let mut accounts = Accounts::default();
+    let summary = process_str(truncated, &mut accounts, &config).unwrap();
+    assert_eq!(accounts.get(&1).unwrap().total, dec!(5.5));
+    let warning = summary.control_warning.unwrap();
+    assert!(warning.contains("mismatch"), "{warning}");
+
+    // every reader skips the trailer instead of rejecting it
+    let reader = || {
+        ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(complete.as_bytes())
+    };
+    assert_eq!(record_iter(&mut reader()).filter(Result::is_ok).count(), 3);
+    assert_eq!(record_iter(&mut reader()).count(), 3);
+    let problems = validate::validate(&mut reader(), &Default::default()).unwrap();
+    assert_eq!(problems, vec![]);
+    assert_eq!(stats::collect_stats(&mut reader()).malformed, 0);
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), complete).unwrap();
+    let mut accounts = Accounts::default();
+    let path = file.path().to_str().unwrap();
+    let summary = parallel::process_txs_mmap(path, &mut accounts, &Default::default()).unwrap();
+    assert_eq!((summary.rows, summary.malformed), (3, 0));
+
+    // an invalid trailer is still rejected
+    let invalid = "type, client, tx, amount\ntrailer, , three, 1.0\n";
+    let mut reader = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(invalid.as_bytes());
+    assert_eq!(record_iter(&mut reader).filter(Result::is_err).count(), 1);
+}
+
+#[test]
+fn test_control_total_sidecar() {
+    use control::ControlSource;
+
+    let sidecar = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(sidecar.path(), "records, amount\n2, 3.0\n").unwrap();
+    let mut config = config::ProcessConfig::default();
+    config.control.source = ControlSource::Sidecar(sidecar.path().to_path_buf());
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+";
+    let mut accounts = Accounts::default();
+    process_str(input, &mut accounts, &config).unwrap();
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.5
+";
+    let mut accounts = Accounts::default();
+    assert!(process_str(input, &mut accounts, &config).is_err());
+}
+
+#[test]
+fn test_amount_formats_normalize() {
+    use parse::AmountFormat;
+
+    let plain = AmountFormat::default();
+    let cents = AmountFormat {
+        minor_units: Some(2),
+        ..Default::default()
+    };
+    let european = AmountFormat {
+        decimal_separator: ',',
+        thousands_separator: Some('.'),
+        ..Default::default()
+    };
+    let grouped = AmountFormat {
+        thousands_separator: Some(','),
+        ..Default::default()
+    };
+
+    assert_eq!(plain.parse("1234.50").unwrap(), dec!(1234.50));
+    assert_eq!(cents.parse("123450").unwrap(), dec!(1234.50));
+    assert_eq!(european.parse("1.234,50").unwrap(), dec!(1234.50));
+    assert_eq!(european.parse("100,50").unwrap(), dec!(100.50));
+    assert_eq!(grouped.parse("1,234.50").unwrap(), dec!(1234.50));
+    assert_eq!(cents.parse("-5").unwrap(), dec!(-0.05));
+
+    // minor units must be integers
+    assert!(cents.parse("1234.50").is_err());
+    assert!(european.parse("1,234,50").is_err());
+    assert!(plain.parse("1,234.50").is_err());
+
+    // ambiguous formats are rejected up front
+    assert_eq!(AmountFormat::new(None, ',', Some('.')).unwrap(), european);
+    assert!(AmountFormat::new(None, ',', Some(',')).is_err());
+    assert!(AmountFormat::new(None, '1', None).is_err());
+    assert!(AmountFormat::new(Some(28), '.', None).is_ok());
+    assert!(AmountFormat::new(Some(29), '.', None).is_err());
+}
+
+#[test]
+fn test_process_european_amounts() {
+    let mut config = config::ProcessConfig::default();
+    config.input.amount = parse::AmountFormat {
+        decimal_separator: ',',
+        thousands_separator: Some('.'),
+        ..Default::default()
+    };
+
+    // decimal commas have to be quoted
+    let input = r#"type,client,tx,amount
+deposit,1,1,"1.000,50"
+withdrawal,1,2,"100,25"
+"#;
+    let mut accounts = Accounts::default();
+    process_str(input, &mut accounts, &config).unwrap();
+    assert_eq!(accounts.get(&1).unwrap().available, dec!(900.25));
+
+    // the serde path normalizes the same way
+    let mut reader = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let txs: Vec<TxType> = reader
+        .deserialize::<CsvRow>()
+        .map(|row| TxType::from_row(row.unwrap(), &config.input.amount).unwrap())
+        .collect();
+    let mut reader = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let expected: Vec<TxType> = parse::ByteRecordIter::new(&mut reader, config.input.amount)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(txs, expected);
+}
+
+#[test]
+fn test_encoded_inputs() {
+    use config::{InputConfig, InputEncoding};
+
+    for (path, encoding) in [
+        ("bom_utf8_test.csv", InputEncoding::Utf8),
+        ("bom_utf16_test.csv", InputEncoding::Utf8),
+        ("latin1_test.csv", InputEncoding::Latin1),
+        ("windows1252_test.csv", InputEncoding::Windows1252),
+        // a BOM takes precedence over the configured encoding
+        ("bom_utf8_test.csv", InputEncoding::Windows1252),
+    ] {
+        let input = InputConfig {
+            encoding,
+            ..Default::default()
+        };
+        let mut reader = path_reader_with(path, &input).unwrap();
+        let transactions: anyhow::Result<Vec<TxType>> = record_iter(&mut reader).collect();
+        let transactions = transactions.unwrap_or_else(|err| panic!("{path}: {err:#}"));
+        assert_eq!(transactions.len(), 3, "{path}");
+
+        let mut accounts = Accounts::default();
+        let mut reader = path_reader_with(path, &input).unwrap();
+        process_txs(&mut reader, &mut accounts);
+        assert_eq!(accounts.get(&1).unwrap().total, dec!(74.5), "{path}");
+        assert_eq!(accounts.get(&2).unwrap().total, dec!(50), "{path}");
+    }
+
+    // without transcoding, rows with Latin-1 text can not be deserialized
+    let mut reader = path_reader("latin1_test.csv").unwrap();
+    assert!(record_iter(&mut reader).all(|tx| tx.is_err()));
+}
+
+#[test]
+fn test_account_rows_order() {
+    use config::AccountOrder;
+
+    let input = "\
+type, client, tx, amount
+deposit, 3, 1, 6.0
+deposit, 1, 2, 20.0
+deposit, 2, 3, 5.0
+deposit, 4, 4, 1.0
+dispute, 4, 4,
+chargeback, 4, 4,
+";
+    let mut accounts = Accounts::default();
+    process_str(input, &mut accounts, &Default::default()).unwrap();
+
+    let clients = |order| -> Vec<u16> {
+        let output = config::OutputConfig {
+            order,
+            ..Default::default()
+        };
+        account_rows(&accounts, &output)
+            .iter()
+            .map(|row| row.client)
+            .collect()
+    };
+    assert_eq!(clients(AccountOrder::ClientId), vec![1, 2, 3, 4]);
+    assert_eq!(clients(AccountOrder::Total), vec![1, 3, 2, 4]);
+    assert_eq!(clients(AccountOrder::LockedFirst), vec![4, 1, 2, 3]);
+    // default order is stable across runs
+    assert_eq!(clients(AccountOrder::default()), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_output_formats() {
+    use output::{OutputFormat, account_writer};
+
+    let mut accounts = Accounts::default();
+    process_tx(
+        TxType::Deposit(types::BalanceChange {
+            client: 2,
+            tx: 1,
+            amount: dec!(10.12345),
+        }),
+        &mut accounts,
+    );
+    process_tx(
+        TxType::Deposit(types::BalanceChange {
+            client: 1,
+            tx: 2,
+            amount: dec!(1),
+        }),
+        &mut accounts,
+    );
+
+    let write = |format| {
+        let mut output = Vec::new();
+        let config = config::OutputConfig {
+            format,
+            ..Default::default()
+        };
+        let mut writer = account_writer(&config, &mut output);
+        for row in account_rows(&accounts, &Default::default()) {
+            writer.write_row(&row).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        String::from_utf8(output).unwrap()
+    };
+
+    let csv = write(OutputFormat::Csv);
+    assert_eq!(
+        csv,
+        "client,available,held,total,locked\n1,1,0,1,false\n2,10.1234,0,10.1234,false\n"
+    );
+
+    let json = write(OutputFormat::Json);
+    let rows: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        rows,
+        serde_json::json!([
+            {"client": 1, "available": "1.0000", "held": "0.0000", "total": "1.0000", "locked": false},
+            {"client": 2, "available": "10.1234", "held": "0.0000", "total": "10.1234", "locked": false},
+        ])
+    );
+
+    let ndjson = write(OutputFormat::Ndjson);
+    let lines: Vec<serde_json::Value> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.as_array().unwrap(), &lines);
+
+    // empty output is still a valid JSON array
+    let mut output = Vec::new();
+    let json = config::OutputConfig {
+        format: OutputFormat::Json,
+        ..Default::default()
+    };
+    account_writer(&json, &mut output).finish().unwrap();
+    assert_eq!(output, b"[]\n");
+
+    // JSON amounts have the configured precision and rounding, like CSV amounts
+    let mut precise = Accounts::default();
+    process_tx(
+        TxType::Deposit(types::BalanceChange {
+            client: 1,
+            tx: 1,
+            amount: dec!(1.234567),
+        }),
+        &mut precise,
+    );
+    for (precision, expected) in [(6, "1.234567"), (5, "1.23457"), (2, "1.23")] {
+        let mut output = Vec::new();
+        let config = config::OutputConfig {
+            precision,
+            rounding: config::Rounding::HalfUp,
+            ..json.clone()
+        };
+        write_accounts(&mut output, &precise, &config).unwrap();
+        let rows: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(rows[0]["available"], expected, "precision {precision}");
+    }
+}
+
+#[test]
+fn test_write_atomic() {
+    use output::write_atomic;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.csv");
+    let mut accounts = Accounts::default();
+    process_str(
+        "type, client, tx, amount\ndeposit, 1, 1, 2.5\n",
+        &mut accounts,
+        &Default::default(),
+    )
+    .unwrap();
+
+    write_atomic(&path, |writer| {
+        write_accounts(writer, &accounts, &Default::default())
+    })
+    .unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        written,
+        "client,available,held,total,locked\n1,2.5,0,2.5,false\n"
+    );
+
+    // a failed write leaves the previous output untouched and no temporary files behind
+    let res = write_atomic(&path, |writer| {
+        writer.write_all(b"partial")?;
+        Err(anyhow::anyhow!("Interrupted"))
+    });
+    assert!(res.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_journal_running_balances() {
+    use journal::JournalWriter;
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+withdrawal, 1, 2, 20.0
+dispute, 1, 1,
+dispute, 2, 1,
+chargeback, 1, 1,
+deposit, 1, 3, 5.0
+";
+    let mut output = Vec::new();
+    let mut accounts = Accounts::default();
+    let mut journal = JournalWriter::new(&mut output);
+    process_str_observed(input, &mut accounts, &mut journal).unwrap();
+    journal.finish().unwrap();
+
+    let journal = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = journal.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "line,tx,client,type,outcome,reason,available,held,total,locked",
+            "2,1,1,deposit,applied,,10.0,0,10.0,false",
+            "3,2,1,withdrawal,ignored,insufficient funds,10.0,0,10.0,false",
+            "4,1,1,dispute,applied,,0.0,10.0,10.0,false",
+            "5,1,2,dispute,ignored,unknown account,0,0,0,false",
+            "6,1,1,chargeback,applied,,0.0,0.0,0.0,true",
+            "7,3,1,deposit,ignored,account is locked,0.0,0.0,0.0,true",
+        ]
+    );
+}
+
+#[test]
+fn test_client_statement() {
+    use statement::StatementCollector;
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 3.0
+deposit, 1, 3, 4.0
+dispute, 1, 1,
+resolve, 1, 1,
+dispute, 1, 3,
+chargeback, 1, 3,
+withdrawal, 1, 4, 1.0
+";
+    let mut accounts = Accounts::default();
+    let mut collector = StatementCollector::new(1, &accounts);
+    process_str_observed(input, &mut accounts, &mut collector).unwrap();
+    let statement = collector.finish(&accounts);
+
+    assert_eq!(statement.opening, Default::default());
+    assert_eq!(
+        statement.entries.iter().map(|e| e.line).collect::<Vec<_>>(),
+        vec![2, 4, 5, 6, 7, 8, 9]
+    );
+    // disputes carry the amount of the deposit
+    assert_eq!(statement.entries[2].amount, Some(dec!(10.0)));
+    assert_eq!(
+        statement.entries[6].outcome,
+        TxOutcome::Ignored(IgnoreReason::AccountLocked)
+    );
+    let disputes: Vec<_> = statement
+        .disputes
+        .iter()
+        .map(|dispute| (dispute.tx, dispute.status()))
+        .collect();
+    assert_eq!(disputes, vec![(1, "resolved"), (3, "charged back")]);
+    assert_eq!(statement.closing.total, dec!(10.0));
+    assert!(statement.closing.locked);
+
+    let mut output = Vec::new();
+    statement.write_csv(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 10);
+    assert_eq!(lines[1], ",,opening,,,0,0,0,false");
+    assert_eq!(lines[9], ",,closing,,,10.0,0.0,10.0,true");
+}
+
+#[test]
+fn test_open_disputes_report() {
+    use disputes::{DisputeReport, DisputeTracker};
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 1, 2, 2.5
+deposit, 2, 3, 7.0
+deposit, 2, 4, 1.0
+dispute, 1, 1,
+dispute, 1, 2,
+dispute, 2, 3,
+dispute, 2, 4,
+resolve, 2, 4,
+dispute, 2, 4,
+chargeback, 1, 2,
+";
+    let mut accounts = Accounts::default();
+    let mut tracker = DisputeTracker::default();
+    process_str_observed(input, &mut accounts, &mut tracker).unwrap();
+    let report = DisputeReport::new(&accounts, &tracker);
+
+    let disputes: Vec<_> = report
+        .disputes
+        .iter()
+        .map(|dispute| (dispute.client, dispute.tx, dispute.amount, dispute.line))
+        .collect();
+    assert_eq!(
+        disputes,
+        vec![
+            (1, 1, dec!(10.0), Some(6)),
+            (2, 3, dec!(7.0), Some(8)),
+            // the latest dispute of a resolved deposit
+            (2, 4, dec!(1.0), Some(11)),
+        ]
+    );
+    for client in &report.clients {
+        assert_eq!(client.amount, client.held);
+        assert_eq!(client.held, accounts[&client.client].held);
+    }
+    assert_eq!(report.clients[1].disputes, 2);
+
+    let mut output = Vec::new();
+    report.write_csv(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(
+        output.lines().take(2).collect::<Vec<_>>(),
+        vec![
+            "client,tx,amount,line,client_total,client_held",
+            "1,1,10.0,6,10.0,10.0"
+        ]
+    );
+
+    // held opening balances have no disputes behind them
+    let opening = "client,available,held,total,locked\n1,0,5.0,5.0,false\n";
+    let mut accounts =
+        opening::opening_accounts(diff::read_accounts(opening.as_bytes()).unwrap(), 4).unwrap();
+    let mut tracker = DisputeTracker::default();
+    process_str_observed(input, &mut accounts, &mut tracker).unwrap();
+    let report = DisputeReport::new(&accounts, &tracker);
+    assert_eq!(report.clients[0].amount, dec!(10.0));
+    assert_eq!(report.clients[0].held, dec!(15.0));
+    assert_eq!(report.clients[0].undisputed(), dec!(5.0));
+    assert_eq!(report.clients[1].undisputed(), dec!(0));
+}
+
+#[test]
+fn test_ledger_trial_balance() {
+    use ledger::{LedgerAccount, LedgerWriter};
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 1, 2, 2.5
+withdrawal, 1, 3, 4.0
+withdrawal, 1, 4, 40.0
+deposit, 2, 5, 7.0
+dispute, 2, 5,
+resolve, 2, 5,
+dispute, 1, 2,
+dispute, 2, 5,
+chargeback, 2, 5,
+deposit, 3, 6, 1.0
+dispute, 3, 6,
+";
+    let mut output = Vec::new();
+    let mut accounts = Accounts::default();
+    let mut ledger = LedgerWriter::new(&mut output);
+    process_str_observed(input, &mut accounts, &mut ledger).unwrap();
+
+    let trial_balance = ledger.trial_balance().clone();
+    assert_eq!(trial_balance.total(), dec!(0));
+    // client accounts carry credit balances
+    for (client, account) in &accounts {
+        let available = trial_balance.balance(LedgerAccount::ClientAvailable(*client));
+        let held = trial_balance.balance(LedgerAccount::ClientHeld(*client));
+        assert_eq!(-available, account.available, "client {client}");
+        assert_eq!(-held, account.held, "client {client}");
+    }
+    let total: Decimal = accounts.values().map(|account| account.total).sum();
+    // the chargeback pays the 7.0 of client 2 back out of settlement cash
+    assert_eq!(
+        trial_balance.balance(LedgerAccount::SettlementCash),
+        dec!(9.5)
+    );
+    assert_eq!(total, trial_balance.balance(LedgerAccount::SettlementCash));
+
+    ledger.finish().unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    // the rejected withdrawal has no entry
+    assert_eq!(lines.len(), 1 + 2 * 11);
+    assert_eq!(lines[0], "entry,line,tx,type,account,debit,credit");
+    assert_eq!(lines[1], "1,2,1,deposit,settlement_cash,10.0,");
+    assert_eq!(lines[2], "1,2,1,deposit,client:1:available,,10.0");
+
+    // a chargeback of withdrawn funds writes off the negative total of the client as a loss
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 5.0
+deposit, 1, 2, 1.0
+withdrawal, 1, 3, 5.5
+dispute, 1, 1,
+chargeback, 1, 1,
+";
+    let mut accounts = Accounts::default();
+    let mut ledger = LedgerWriter::new(Vec::new());
+    process_str_observed(input, &mut accounts, &mut ledger).unwrap();
+    let trial_balance = ledger.trial_balance();
+    assert_eq!(accounts[&1].total, dec!(-4.5));
+    assert_eq!(trial_balance.total(), dec!(0));
+    assert_eq!(
+        trial_balance.balance(LedgerAccount::ChargebackLosses),
+        dec!(4.5)
+    );
+    // the written off client owes nothing in the ledger, and the cash matches the accounts
+    assert_eq!(
+        trial_balance.balance(LedgerAccount::ClientAvailable(1)),
+        dec!(0)
+    );
+    assert_eq!(
+        trial_balance.balance(LedgerAccount::SettlementCash),
+        accounts[&1].total
+    );
+}
+
+#[test]
+fn test_diff_account_snapshots() {
+    use diff::{diff_accounts, read_accounts};
+
+    let before = read_accounts(
+        "\
+client,available,held,total,locked
+1,1.5,0,1.5,false
+2,3.0,1.0,4.0,false
+3,2.0,0,2.0,false
+"
+        .as_bytes(),
+    )
+    .unwrap();
+    let after = read_accounts(
+        "\
+[
+{\"client\":1,\"available\":\"1.50\",\"held\":\"0\",\"total\":\"1.50\",\"locked\":false},
+{\"client\":2,\"available\":\"3.0\",\"held\":\"0\",\"total\":\"3.0\",\"locked\":true},
+{\"client\":4,\"available\":\"5\",\"held\":\"0\",\"total\":\"5\",\"locked\":false}
+]
+"
+        .as_bytes(),
+    )
+    .unwrap();
+    let ndjson = read_accounts(
+        "{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(ndjson[&1], before[&1]);
+
+    let diff = diff_accounts(&before, &after);
+    assert!(!diff.is_empty());
+    assert!(diff_accounts(&before, &before).is_empty());
+    assert_eq!(
+        diff.added.iter().map(|row| row.client).collect::<Vec<_>>(),
+        vec![4]
+    );
+    assert_eq!(
+        diff.removed
+            .iter()
+            .map(|row| row.client)
+            .collect::<Vec<_>>(),
+        vec![3]
+    );
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].held(), dec!(-1.0));
+    assert_eq!(diff.changed[0].total(), dec!(-1.0));
+    assert!(diff.changed[0].locked());
+
+    // extreme balances saturate instead of overflowing
+    let mut change = diff.changed[0].clone();
+    change.before.available = Decimal::MIN;
+    change.after.available = Decimal::MAX;
+    assert_eq!(change.available(), Decimal::MAX);
+
+    let mut output = Vec::new();
+    diff.write_text(&mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
++ client 4: available 5, held 0, total 5
+- client 3: available 2.0, held 0, total 2.0
+~ client 2: held -1.0, total -1.0, locked false -> true
+"
+    );
+
+    // duplicate clients are rejected
+    assert!(
+        read_accounts(
+            "client,available,held,total,locked\n1,0,0,0,false\n1,0,0,0,false\n".as_bytes()
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_output_rounding() {
+    use config::{OutputConfig, Rounding};
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 1.00005
+deposit, 1, 2, 2.00015
+dispute, 1, 2,
+deposit, 2, 3, 1.00004
+withdrawal, 2, 4, 0.99999
+dispute, 2, 3,
+deposit, 3, 5, 0.2
+withdrawal, 3, 6, 0.1
+dispute, 3, 5,
+";
+    let mut accounts = Accounts::default();
+    process_str(input, &mut accounts, &Default::default()).unwrap();
+    let before = accounts.clone();
+
+    let rows = |rounding, precision| -> Vec<(Decimal, Decimal, Decimal)> {
+        let output = OutputConfig {
+            rounding,
+            precision,
+            ..Default::default()
+        };
+        account_rows(&accounts, &output)
+            .iter()
+            .map(|row| (row.available, row.held, row.total))
+            .collect()
+    };
+    // client 2 and 3 have negative available balances of -0.99999 and -0.1,
+    // and client 2 a total of 0.00005
+    assert_eq!(
+        rows(Rounding::HalfEven, 4),
+        vec![
+            (dec!(1.0000), dec!(2.0002), dec!(3.0002)),
+            (dec!(-1.0000), dec!(1.0000), dec!(0.0000)),
+            (dec!(-0.1), dec!(0.2), dec!(0.1)),
+        ]
+    );
+    assert_eq!(
+        rows(Rounding::HalfUp, 4),
+        vec![
+            (dec!(1.0001), dec!(2.0002), dec!(3.0002)),
+            (dec!(-1.0000), dec!(1.0000), dec!(0.0001)),
+            (dec!(-0.1), dec!(0.2), dec!(0.1)),
+        ]
+    );
+    assert_eq!(
+        rows(Rounding::Truncate, 4),
+        vec![
+            (dec!(1.0000), dec!(2.0001), dec!(3.0002)),
+            (dec!(-0.9999), dec!(1.0000), dec!(0.0000)),
+            (dec!(-0.1), dec!(0.2), dec!(0.1)),
+        ]
+    );
+    assert_eq!(
+        rows(Rounding::HalfUp, 0),
+        vec![
+            (dec!(1), dec!(2), dec!(3)),
+            (dec!(-1), dec!(1), dec!(0)),
+            (dec!(0), dec!(0), dec!(0)),
+        ]
+    );
+    assert_eq!(rows(Rounding::Truncate, 0)[1].0, dec!(0));
+
+    // rounding the output leaves the engine state untouched
+    let mut output = Vec::new();
+    write_accounts(&mut output, &accounts, &Default::default()).unwrap();
+    assert_eq!(accounts, before);
+    assert_eq!(accounts[&1].available, dec!(1.00005));
+}
+
+#[test]
+fn test_flush_changed_accounts() {
+    use output::{CsvAccountWriter, DeltaWriter};
+
+    let mut accounts = Accounts::default();
+    process_str(
+        "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 3, 3, 1.0
+",
+        &mut accounts,
+        &Default::default(),
+    )
+    .unwrap();
+    // the account opened by the rejected withdrawal is new as well
+    assert_eq!(
+        accounts.dirty().iter().copied().collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    let mut output = Vec::new();
+    let mut writer = CsvAccountWriter::new(&mut output);
+    assert_eq!(
+        flush_accounts(&mut writer, &mut accounts, &Default::default()).unwrap(),
+        3
+    );
+    assert!(accounts.dirty().is_empty());
+
+    // ignored transactions leave accounts clean
+    process_str(
+        "\
+type, client, tx, amount
+withdrawal, 2, 4, 50.0
+dispute, 1, 1,
+dispute, 4, 9,
+",
+        &mut accounts,
+        &Default::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        flush_accounts(&mut writer, &mut accounts, &Default::default()).unwrap(),
+        1
+    );
+    assert_eq!(
+        flush_accounts(&mut writer, &mut accounts, &Default::default()).unwrap(),
+        0
+    );
+    writer.finish().unwrap();
+    drop(writer);
+    let lines: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "client,available,held,total,locked",
+            "1,10.0,0,10.0,false",
+            "2,5.0,0,5.0,false",
+            "3,0,0,0,false",
+            "1,0.0,10.0,10.0,false",
+        ]
+    );
+
+    // flush every two transactions
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 2.0
+deposit, 1, 3, 3.0
+withdrawal, 1, 4, 100.0
+deposit, 3, 5, 4.0
+";
+    let mut output = Vec::new();
+    let mut accounts = Accounts::default();
+    let output_config = config::OutputConfig {
+        format: output::OutputFormat::Ndjson,
+        ..Default::default()
+    };
+    let mut delta = DeltaWriter::new(&mut output, output_config, 2);
+    process_str_observed(input, &mut accounts, &mut delta).unwrap();
+    delta.finish(&mut accounts).unwrap();
+    let clients: Vec<u16> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<AccountRow>(line).unwrap().client)
+        .collect();
+    assert_eq!(clients, vec![1, 2, 1, 3]);
+
+    // every JSON flush is a complete array
+    let mut output = Vec::new();
+    let mut accounts = Accounts::default();
+    let output_config = config::OutputConfig {
+        format: output::OutputFormat::Json,
+        ..Default::default()
+    };
+    let mut delta = DeltaWriter::new(&mut output, output_config, 2);
+    process_str_observed(input, &mut accounts, &mut delta).unwrap();
+    delta.finish(&mut accounts).unwrap();
+    let batches: Vec<Vec<u16>> = serde_json::Deserializer::from_slice(&output)
+        .into_iter::<Vec<AccountRow>>()
+        .map(|batch| batch.unwrap().iter().map(|row| row.client).collect())
+        .collect();
+    assert_eq!(batches, vec![vec![1, 2], vec![1], vec![3]]);
+}
+
+#[test]
+fn test_validate_input() {
+    use validate::validate;
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 1, 2,
+transfer, 1, 3, 1.0
+withdrawal, 2, 1, 1.0
+withdrawal, 2, 4, 1.0
+dispute, 1, 9,
+dispute, 2, 1,
+dispute, 2, 4,
+resolve, 1, 1,
+dispute, 1, 1,
+dispute, 1, 1,
+chargeback, 1, 1,
+resolve, 1, 1,
+dispute, 1, 1,
+";
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let problems: Vec<String> = validate(&mut reader, &Default::default())
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        problems,
+        vec![
+            "line 3: Missing amount for deposit",
+            "line 4: Unknown transaction type: transfer",
+            "line 5: Duplicate tx 1, first used on line 2",
+            "line 7: Dispute of unknown tx 9",
+            "line 8: Dispute by client 2 of tx 1 of client 1",
+            "line 9: Dispute of withdrawal tx 4",
+            "line 10: Resolve of tx 1 without a dispute",
+            "line 12: Dispute of tx 1 that is already disputed",
+            "line 14: Resolve of tx 1 without a dispute",
+            "line 15: Dispute of tx 1 that is already charged back",
+        ]
+    );
+
+    // the line-numbered records match the plain ones
+    let mut reader = path_reader("all_types.csv").unwrap();
+    let numbered: Vec<(u64, TxType)> = numbered_record_iter(&mut reader, &Default::default())
+        .unwrap()
+        .map(|(line, tx)| (line, tx.unwrap()))
+        .collect();
+    let mut reader = path_reader("all_types.csv").unwrap();
+    let plain: Vec<TxType> = record_iter(&mut reader).map(Result::unwrap).collect();
+    assert_eq!(
+        numbered
+            .iter()
+            .map(|(_, tx)| tx.clone())
+            .collect::<Vec<_>>(),
+        plain
+    );
+    assert_eq!(
+        numbered.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+        vec![2, 3, 4, 5, 6]
+    );
+}
+
+#[test]
+fn test_collect_stats() {
+    use stats::{StatsCollector, collect_stats};
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 30.0
+deposit, 1, 3, 10.0
+deposit, 3, 4, 5.0
+withdrawal, 1, 5, 20.0
+withdrawal, 2, 6, 40.0
+dispute, 1, 1,
+dispute, 2, 2,
+resolve, 1, 1,
+chargeback, 2, 2,
+refund, 4, 7, 1.0
+";
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let stats = collect_stats(&mut reader);
+    assert_eq!(stats.rows, 11);
+    assert_eq!(stats.malformed, 1);
+    assert_eq!(
+        (
+            stats.counts.deposit,
+            stats.counts.withdrawal,
+            stats.counts.dispute,
+            stats.counts.resolve,
+            stats.counts.chargeback
+        ),
+        (4, 2, 2, 1, 1)
+    );
+    // the client of the malformed row is not counted
+    assert_eq!(stats.clients, 3);
+    assert_eq!(stats.deposited, dec!(55.0));
+    assert_eq!(stats.withdrawn, dec!(60.0));
+    assert_eq!(stats.dispute_rate, 0.5);
+    assert_eq!(stats.chargeback_rate, 0.25);
+    // the withdrawal that is rejected when applied is still among the largest
+    let largest: Vec<(u32, Decimal)> = stats.largest.iter().map(|tx| (tx.tx, tx.amount)).collect();
+    assert_eq!(
+        largest,
+        vec![
+            (6, dec!(40.0)),
+            (2, dec!(30.0)),
+            (5, dec!(20.0)),
+            (1, dec!(10.0)),
+            (3, dec!(10.0)),
+        ]
+    );
+
+    // without deposits, rates are zero rather than undefined
+    let stats = StatsCollector::new(0).finish();
+    assert_eq!(stats.dispute_rate, 0.0);
+    assert!(stats.largest.is_empty());
+
+    let mut output = Vec::new();
+    stats.write_json(&mut output).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(json["deposited"], "0");
+}
+
+#[test]
+fn test_explain_client() {
+    use explain::Explainer;
+    use types::Balances;
+
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 2, 2, 5.0
+withdrawal, 1, 3, 20.0
+dispute, 1, 1,
+dispute, 1, 2,
+";
+    let mut output = Vec::new();
+    let mut accounts = Accounts::default();
+    let mut explainer = Explainer::new(&mut output, 1, &accounts);
+    process_str_observed(input, &mut accounts, &mut explainer).unwrap();
+    explainer.finish().unwrap();
+
+    let explanation = String::from_utf8(output).unwrap();
+    assert_eq!(
+        explanation,
+        "\
+line 2: deposit tx 1 of 10.0, applied
+  before:    available 0, held 0, total 0
+  after:     available 10.0, held 0, total 10.0
+line 4: withdrawal tx 3 of 20.0, ignored: insufficient funds
+  unchanged: available 10.0, held 0, total 10.0
+line 5: dispute tx 1 of 10.0, applied
+  before:    available 10.0, held 0, total 10.0
+  after:     available 0.0, held 10.0, total 10.0
+line 6: dispute tx 2, ignored: unknown deposit
+  unchanged: available 0.0, held 10.0, total 10.0
+Client 1 after 4 transactions: available 0.0, held 10.0, total 10.0
+"
+    );
+    // the explanation ends in the real final state
+    assert!(explanation.ends_with(&format!("{}\n", Balances::of(&accounts, 1))));
+}
+
+#[test]
+fn test_repl_commands() {
+    use repl::Repl;
+
+    let mut repl = Repl::default();
+    let mut output = Vec::new();
+    for command in [
+        "deposit 1 1 10.0",
+        "dispute 1 1",
+        "disputes",
+        "withdrawal 1 2 5.0",
+        "undo",
+        "undo",
+        "account 1",
+        "",
+    ] {
+        assert!(repl.execute(command, &mut output).unwrap());
+    }
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
+applied, available 10.0, held 0, total 10.0
+applied, available 0.0, held 10.0, total 10.0
+client 1 tx 1: 10.0
+ignored: insufficient funds, available 0.0, held 10.0, total 10.0
+Undone
+Undone
+available 10.0, held 0, total 10.0
+"
+    );
+
+    // invalid commands leave the accounts unchanged
+    let mut output = Vec::new();
+    let accounts = repl.accounts().clone();
+    assert!(repl.execute("deposit 1 x 1.0", &mut output).is_err());
+    assert!(repl.execute("dispute 1 1 2 3", &mut output).is_err());
+    assert!(repl.execute("transfer 1 2", &mut output).is_err());
+    assert_eq!(repl.accounts(), &accounts);
+
+    assert!(!repl.execute("quit", &mut output).unwrap());
+}
+
+#[test]
+fn test_settings_file() {
+    use config::{AccountOrder, InputEncoding, ProcessConfig, Rules};
+    use output::OutputFormat;
+    use settings::Settings;
+
+    let settings: Settings = "
+[input]
+encoding = \"latin1\"
+decimal_separator = \",\"
+thousands_separator = \".\"
+
+[output]
+format = \"json\"
+order = \"total\"
+precision = 2
+
+[rules]
+lock_on_chargeback = false
+dispute_requires_funds = true
+"
+    .parse()
+    .unwrap();
+    assert_eq!(settings.input.encoding, InputEncoding::Latin1);
+    assert_eq!(settings.input.amount.decimal_separator, ',');
+    assert_eq!(settings.input.amount.thousands_separator, Some('.'));
+    assert_eq!(settings.output.format, OutputFormat::Json);
+    assert_eq!(settings.output.order, AccountOrder::Total);
+    assert_eq!(settings.output.precision, 2);
+    assert_eq!(
+        settings.rules,
+        Rules {
+            lock_on_chargeback: false,
+            dispute_requires_funds: true,
+        }
+    );
+
+    // missing keys keep their defaults
+    let settings: Settings = "[output]\nprecision = 2\n".parse().unwrap();
+    assert_eq!(settings.output.format, OutputFormat::Csv);
+    assert_eq!(settings.rules, Rules::default());
+
+    for invalid in [
+        "[output]\nformat = \"xml\"\n",
+        "[output]\nprecision = 29\n",
+        "[input]\ndecimal_separator = \",\"\nthousands_separator = \",\"\n",
+        "[rules]\nlock = false\n",
+        "[limits]\n",
+    ] {
+        assert!(invalid.parse::<Settings>().is_err(), "{invalid}");
+    }
+
+    // the rules apply to processing
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+withdrawal, 1, 2, 4.0
+dispute, 1, 1,
+deposit, 2, 3, 10.0
+dispute, 2, 3,
+chargeback, 2, 3,
+deposit, 2, 4, 1.0
+";
+    let config = ProcessConfig {
+        rules: Rules {
+            lock_on_chargeback: false,
+            dispute_requires_funds: true,
+        },
+        ..Default::default()
+    };
+    let mut accounts = Accounts::default();
+    process_str(input, &mut accounts, &config).unwrap();
+    // the dispute of more than the available funds is ignored
+    assert_eq!(accounts[&1].available, dec!(6.0));
+    assert_eq!(accounts[&1].held, dec!(0));
+    // the account stays unlocked after the chargeback
+    assert!(!accounts[&2].locked);
+    assert_eq!(accounts[&2].total, dec!(1.0));
+}
+
+#[test]
+fn test_progress_reports() {
+    use progress::{PROGRESS_ROWS, Progress, ProgressFn, ProgressReporter};
+    use std::time::Duration;
+
+    let mut input = String::from("type, client, tx, amount\n");
+    for tx in 1..=PROGRESS_ROWS + 2 {
+        input += &format!("deposit, 1, {tx}, 1.0\n");
+    }
+    input += "withdrawal, 1, 0, 1000000.0\nbogus, 1, 0, 1.0\n";
+
+    let mut reports = Vec::new();
+    let mut accounts = Accounts::default();
+    let mut callback = ProgressFn(|progress: &Progress| reports.push(progress.clone()));
+    let summary = process_str_observed(&input, &mut accounts, &mut callback).unwrap();
+    assert_eq!(summary.ignored, 1);
+
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].rows, PROGRESS_ROWS);
+    assert!(!reports[0].done);
+    assert!(reports[0].bytes < reports[1].bytes);
+    let last = &reports[1];
+    assert_eq!(last.rows, PROGRESS_ROWS + 4);
+    assert_eq!((last.malformed, last.ignored, last.rejected()), (1, 1, 2));
+    assert_eq!(last.bytes, input.len() as u64);
+    assert!(last.done);
+
+    // the reporter is throttled, but always writes the final progress
+    let mut output = Vec::new();
+    let mut reporter = ProgressReporter::new(&mut output, Some(4096));
+    let mut progress = Progress {
+        rows: 1000,
+        malformed: 1,
+        ignored: 2,
+        bytes: 1024,
+        elapsed: Duration::from_millis(500),
+        done: false,
+    };
+    reporter.on_progress(&progress).unwrap();
+    progress.elapsed = Duration::from_secs(2);
+    reporter.on_progress(&progress).unwrap();
+    progress.elapsed = Duration::from_millis(2500);
+    reporter.on_progress(&progress).unwrap();
+    progress.rows = 4000;
+    progress.bytes = 4096;
+    progress.elapsed = Duration::from_secs(8);
+    progress.done = true;
+    reporter.on_progress(&progress).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
+1000 rows, 500 rows/s, 3 rejected (1 malformed, 2 ignored), 1.0 KiB of 4.0 KiB (25.0%), ETA 6s
+4000 rows, 500 rows/s, 3 rejected (1 malformed, 2 ignored), 4.0 KiB of 4.0 KiB (100.0%), done in 8s
+"
+    );
+}
+
+#[test]
+fn test_strict_mode() {
+    let config = config::ProcessConfig {
+        strict: true,
+        ..Default::default()
+    };
+    let mut accounts = Accounts::default();
+    process_str(
+        "type, client, tx, amount\ndeposit, 1, 1, 10.0\n",
+        &mut accounts,
+        &config,
+    )
+    .unwrap();
+    let before = accounts.clone();
+
+    let rejected = "\
+type, client, tx, amount
+deposit, 1, 2, 5.0
+deposit, 2, 3, 5.0
+withdrawal, 2, 4, 6.0
+deposit, 1, 5, 5.0
+";
+    let err = process_str(rejected, &mut accounts, &config).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "Rejected line 4: withdrawal tx 4 ignored: insufficient funds"
+    );
+    // earlier transactions of the batch are rolled back
+    assert_eq!(accounts, before);
+
+    let malformed = "\
+type, client, tx, amount
+deposit, 1, 2, 5.0
+deposit, 1, 3, five
+";
+    let err = process_str(malformed, &mut accounts, &config).unwrap_err();
+    assert!(
+        format!("{err:#}").starts_with("Rejected line 3: "),
+        "{err:#}"
+    );
+    assert_eq!(accounts, before);
+
+    // without strict mode both batches are processed
+    let mut lenient = before.clone();
+    let summary = process_str(rejected, &mut lenient, &Default::default()).unwrap();
+    assert_eq!(summary.ignored, 1);
+    assert_eq!(lenient[&1].available, dec!(20.0));
+}
+
+#[test]
+fn test_generate_workload() {
+    use generate::{GenerateConfig, generate};
+    use journal::JournalWriter;
+
+    let config = GenerateConfig {
+        clients: 5,
+        rows: 2000,
+        dispute_probability: 0.2,
+        resolve_probability: 0.1,
+        chargeback_probability: 0.05,
+        seed: 42,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    generate(&mut output, &config).unwrap();
+
+    // the same seed generates the same file
+    let mut again = Vec::new();
+    generate(&mut again, &config).unwrap();
+    assert_eq!(output, again);
+    let mut other = Vec::new();
+    generate(
+        &mut other,
+        &GenerateConfig {
+            seed: 43,
+            ..config.clone()
+        },
+    )
+    .unwrap();
+    assert_ne!(output, other);
+
+    let mut reader = ReaderBuilder::new().from_reader(output.as_slice());
+    let stats = stats::collect_stats(&mut reader);
+    assert_eq!(stats.rows, 2000);
+    assert_eq!(stats.malformed, 0);
+    assert_eq!(stats.clients, 5);
+    assert!(stats.counts.dispute > 0 && stats.counts.resolve > 0 && stats.counts.chargeback > 0);
+
+    // disputes, resolves and chargebacks all refer to prior deposits of the same client
+    let mut reader = ReaderBuilder::new().from_reader(output.as_slice());
+    let problems = validate::validate(&mut reader, &Default::default()).unwrap();
+    assert_eq!(problems, vec![]);
+
+    // and are all applied, as are all deposits
+    let mut accounts = Accounts::default();
+    let mut journal = JournalWriter::new(Vec::new());
+    let input = String::from_utf8(output).unwrap();
+    process_str_observed(&input, &mut accounts, &mut journal).unwrap();
+    let journal = journal.finish().unwrap();
+    let mut reader = ReaderBuilder::new().from_reader(journal.as_slice());
+    let mut applied = 0;
+    for row in reader.records() {
+        let row = row.unwrap();
+        if &row[3] != "withdrawal" {
+            assert_eq!(&row[4], "applied", "{row:?}");
+            applied += 1;
+        }
+    }
+    assert!(applied > 0);
+    assert!(accounts.values().any(|account| account.locked));
+
+    for invalid in [
+        GenerateConfig {
+            clients: 0,
+            ..config.clone()
+        },
+        GenerateConfig {
+            deposit_ratio: 0.0,
+            withdrawal_ratio: 0.0,
+            ..config.clone()
+        },
+        GenerateConfig {
+            dispute_probability: 0.9,
+            resolve_probability: 0.2,
+            ..config.clone()
+        },
+    ] {
+        assert!(generate(std::io::sink(), &invalid).is_err(), "{invalid:?}");
+    }
+}
+
+#[test]
+fn test_opening_balances() {
+    use diff::read_accounts;
+    use ledger::{LedgerAccount, LedgerWriter};
+    use opening::opening_accounts;
+
+    // precision of the output the opening balances were written with
+    const PRECISION: u32 = 4;
+
+    let opening = "\
+client,available,held,total,locked
+1,10.0,2.5,12.5,false
+2,1.0,0.0,1.0,true
+";
+    let mut accounts =
+        opening_accounts(read_accounts(opening.as_bytes()).unwrap(), PRECISION).unwrap();
+    // opening accounts are part of the first flush
+    assert_eq!(accounts.dirty().len(), 2);
+
+    let input = "\
+type, client, tx, amount
+withdrawal, 1, 1, 10.0
+deposit, 2, 2, 5.0
+deposit, 3, 3, 5.0
+";
+    process_str(input, &mut accounts, &Default::default()).unwrap();
+    assert_eq!(accounts[&1].available, dec!(0.0));
+    assert_eq!(accounts[&1].held, dec!(2.5));
+    assert_eq!(accounts[&1].total, dec!(2.5));
+    // locked accounts stay locked
+    assert!(accounts[&2].locked);
+    assert_eq!(accounts[&2].total, dec!(1.0));
+    assert_eq!(accounts[&3].total, dec!(5.0));
+
+    let invalid = "client,available,held,total,locked\n1,10.0,2.5,12.0,false\n";
+    let err = opening_accounts(read_accounts(invalid.as_bytes()).unwrap(), PRECISION).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Total 12.0 of client 1 is not available 10.0 plus held 2.5"
+    );
+
+    // rounded output may be one unit off, and the total is derived from the exact balances
+    let rounded = "client,available,held,total,locked\n1,0.0002,0.0002,0.0003,false\n";
+    let accounts = opening_accounts(read_accounts(rounded.as_bytes()).unwrap(), PRECISION).unwrap();
+    assert_eq!(accounts[&1].total, dec!(0.0004));
+    // whole amounts of the output are exact, a whole unit off is not rounding
+    let whole = "client,available,held,total,locked\n1,99,0,100,false\n";
+    assert!(opening_accounts(read_accounts(whole.as_bytes()).unwrap(), PRECISION).is_err());
+    let whole = "client,available,held,total,locked\n1,99,1,100,false\n";
+    let accounts = opening_accounts(read_accounts(whole.as_bytes()).unwrap(), PRECISION).unwrap();
+    assert_eq!(accounts[&1].total, dec!(100));
+    // coarser output is rounded by more
+    let cents = "client,available,held,total,locked\n1,0.02,0.02,0.03,false\n";
+    assert!(opening_accounts(read_accounts(cents.as_bytes()).unwrap(), PRECISION).is_err());
+    assert!(opening_accounts(read_accounts(cents.as_bytes()).unwrap(), 2).is_ok());
+    let overflow = format!(
+        "client,available,held,total,locked\n1,{},{},{},false\n",
+        Decimal::MAX,
+        Decimal::MAX,
+        Decimal::MAX
+    );
+    assert!(opening_accounts(read_accounts(overflow.as_bytes()).unwrap(), PRECISION).is_err());
+
+    // the ledger posts the opening balances, so that its trial balance matches the accounts
+    let mut accounts =
+        opening_accounts(read_accounts(opening.as_bytes()).unwrap(), PRECISION).unwrap();
+    let mut ledger = LedgerWriter::new(Vec::new());
+    ledger.post_opening(&accounts).unwrap();
+    process_str_observed(input, &mut accounts, &mut ledger).unwrap();
+    let trial_balance = ledger.trial_balance();
+    assert_eq!(trial_balance.total(), dec!(0));
+    for (client, account) in &accounts {
+        let available = trial_balance.balance(LedgerAccount::ClientAvailable(*client));
+        let held = trial_balance.balance(LedgerAccount::ClientHeld(*client));
+        assert_eq!(-available, account.available, "client {client}");
+        assert_eq!(-held, account.held, "client {client}");
+    }
+    let total: Decimal = accounts.values().map(|account| account.total).sum();
+    assert_eq!(total, trial_balance.balance(LedgerAccount::SettlementCash));
+}
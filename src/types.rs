@@ -1,10 +1,66 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
 
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
-/// A map of user ids => account data
-pub type Accounts = HashMap<u16, ClientAccount>;
+use crate::parse::AmountFormat;
+
+/// A map of user ids => account data, tracking the clients changed since the last flush
+#[derive(Debug, Default, Clone)]
+pub struct Accounts {
+    accounts: HashMap<u16, ClientAccount>,
+    dirty: BTreeSet<u16>,
+}
+
+impl Accounts {
+    /// Mark the account of a client as changed
+    pub fn mark_dirty(&mut self, client: u16) {
+        self.dirty.insert(client);
+    }
+
+    /// Clients changed since the last flush, in ascending order
+    pub fn dirty(&self) -> &BTreeSet<u16> {
+        &self.dirty
+    }
+
+    /// Return the changed clients and clear the dirty set
+    pub fn take_dirty(&mut self) -> BTreeSet<u16> {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+impl Deref for Accounts {
+    type Target = HashMap<u16, ClientAccount>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.accounts
+    }
+}
+
+impl DerefMut for Accounts {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.accounts
+    }
+}
+
+impl<'a> IntoIterator for &'a Accounts {
+    type Item = (&'a u16, &'a ClientAccount);
+    type IntoIter = std::collections::hash_map::Iter<'a, u16, ClientAccount>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.accounts.iter()
+    }
+}
+
+/// Accounts are equal if their balances are, regardless of what has been flushed
+#[cfg(test)]
+impl PartialEq for Accounts {
+    fn eq(&self, other: &Self) -> bool {
+        self.accounts == other.accounts
+    }
+}
 
 /// State of a dispute
 #[derive(Debug, Clone, PartialEq)]
@@ -36,6 +92,7 @@ impl From<BalanceChange> for Deposit {
 
 /// Dispute transaction
 #[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Dispute {
     pub client: u16,
@@ -44,6 +101,7 @@ pub struct Dispute {
 
 /// Balance change for deposit and withdrawal
 #[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
 pub struct BalanceChange {
     pub client: u16,
     pub tx: u32,
@@ -64,6 +122,7 @@ impl arbitrary::Arbitrary<'_> for BalanceChange {
 
 /// A type of a parsed transaction in csv.
 #[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum TxType {
     Deposit(BalanceChange),
@@ -73,6 +132,97 @@ pub enum TxType {
     Chargeback(Dispute),
 }
 
+impl TxType {
+    /// Name of the transaction type, as written in csv
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxType::Deposit(_) => "deposit",
+            TxType::Withdrawal(_) => "withdrawal",
+            TxType::Dispute(_) => "dispute",
+            TxType::Resolve(_) => "resolve",
+            TxType::Chargeback(_) => "chargeback",
+        }
+    }
+
+    /// Client the transaction belongs to
+    pub fn client(&self) -> u16 {
+        match self {
+            TxType::Deposit(change) | TxType::Withdrawal(change) => change.client,
+            TxType::Dispute(dispute) | TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                dispute.client
+            }
+        }
+    }
+
+    /// Transaction id, which refers to a deposit for disputes, resolves and chargebacks
+    pub fn tx(&self) -> u32 {
+        match self {
+            TxType::Deposit(change) | TxType::Withdrawal(change) => change.tx,
+            TxType::Dispute(dispute) | TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                dispute.tx
+            }
+        }
+    }
+
+    /// Amount of deposits and withdrawals
+    pub fn amount(&self) -> Option<Decimal> {
+        match self {
+            TxType::Deposit(change) | TxType::Withdrawal(change) => Some(change.amount),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of processing a single transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TxOutcome {
+    /// Balances were updated
+    Applied,
+    /// Transaction was ignored and balances are unchanged
+    Ignored(IgnoreReason),
+}
+
+/// `applied`, or `ignored: ` and the reason
+impl fmt::Display for TxOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxOutcome::Applied => write!(f, "applied"),
+            TxOutcome::Ignored(reason) => write!(f, "ignored: {reason}"),
+        }
+    }
+}
+
+/// Reason for ignoring a transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoreReason {
+    /// Deposits and withdrawals are not allowed on locked accounts
+    AccountLocked,
+    /// Available balance is lower than the withdrawal amount
+    InsufficientFunds,
+    /// Client has no account
+    UnknownAccount,
+    /// Client has no deposit with the transaction id
+    UnknownTx,
+    /// Deposit is already disputed or charged back
+    AlreadyDisputed,
+    /// Deposit is not disputed
+    NotDisputed,
+}
+
+impl std::fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            IgnoreReason::AccountLocked => "account is locked",
+            IgnoreReason::InsufficientFunds => "insufficient funds",
+            IgnoreReason::UnknownAccount => "unknown account",
+            IgnoreReason::UnknownTx => "unknown deposit",
+            IgnoreReason::AlreadyDisputed => "deposit is already disputed or charged back",
+            IgnoreReason::NotDisputed => "deposit is not disputed",
+        };
+        f.write_str(reason)
+    }
+}
+
 /// State of a client account.
 #[derive(Debug, Default, Clone, Serialize)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -104,6 +254,50 @@ impl ClientAccount {
     }
 }
 
+/// Balances of a client at a point in time
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Balances {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl Balances {
+    /// Current balances of a client, zero if the client has no account
+    pub fn of(accounts: &Accounts, client: u16) -> Self {
+        accounts.get(&client).map(Self::from).unwrap_or_default()
+    }
+}
+
+/// Balances rounded to 4dp, as in `available 1.5, held 0, total 1.5, locked`
+impl fmt::Display for Balances {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "available {}, held {}, total {}",
+            self.available.round_dp(4),
+            self.held.round_dp(4),
+            self.total.round_dp(4)
+        )?;
+        if self.locked {
+            write!(f, ", locked")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&ClientAccount> for Balances {
+    fn from(account: &ClientAccount) -> Self {
+        Self {
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
 // We need this because internal tagging does not work with csv:
 // https://github.com/BurntSushi/rust-csv/issues/211
 #[derive(Debug, Deserialize)]
@@ -112,27 +306,36 @@ pub struct CsvRow {
     tx_type: String,
     client: u16,
     tx: u32,
-    amount: Option<Decimal>,
+    // parsed according to the configured `AmountFormat`
+    amount: Option<String>,
 }
 
 impl TryFrom<CsvRow> for TxType {
     type Error = anyhow::Error;
 
     fn try_from(row: CsvRow) -> Result<Self, Self::Error> {
+        TxType::from_row(row, &AmountFormat::default())
+    }
+}
+
+impl TxType {
+    /// Convert a deserialized row, parsing the amount in the given format
+    pub fn from_row(row: CsvRow, format: &AmountFormat) -> anyhow::Result<Self> {
+        let amount = row
+            .amount
+            .as_deref()
+            .map(|amount| format.parse(amount))
+            .transpose()?;
         match row.tx_type.as_str() {
             "deposit" => Ok(TxType::Deposit(BalanceChange {
                 client: row.client,
                 tx: row.tx,
-                amount: row
-                    .amount
-                    .ok_or(anyhow::anyhow!("Missing amount for deposit"))?,
+                amount: amount.ok_or(anyhow::anyhow!("Missing amount for deposit"))?,
             })),
             "withdrawal" => Ok(TxType::Withdrawal(BalanceChange {
                 client: row.client,
                 tx: row.tx,
-                amount: row
-                    .amount
-                    .ok_or(anyhow::anyhow!("Missing amount for withdrawal"))?,
+                amount: amount.ok_or(anyhow::anyhow!("Missing amount for withdrawal"))?,
             })),
             "dispute" => Ok(TxType::Dispute(Dispute {
                 client: row.client,
@@ -154,7 +357,7 @@ impl TryFrom<CsvRow> for TxType {
 /// Account row for csv output.
 /// We need this explicitly since `flatten` does not work.
 /// https://github.com/BurntSushi/rust-csv/issues/239
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AccountRow {
     pub client: u16,
     #[serde(with = "rust_decimal::serde::str")]
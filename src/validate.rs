@@ -0,0 +1,155 @@
+//! Validation of input files without applying them.
+//!
+//! Rows are checked for structural problems, such as unknown types or missing amounts,
+//! and for referential problems, such as disputes of unknown transactions.
+//! Balances are not tracked, so rejections for insufficient funds are not reported.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use csv::Reader;
+
+use crate::numbered_record_iter;
+use crate::parse::AmountFormat;
+use crate::types::{Dispute, DisputeState, TxType};
+
+/// Problem with a row of the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Line of the row in the input
+    pub line: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Transaction seen by the [Validator]
+#[derive(Debug)]
+struct SeenTx {
+    line: u64,
+    client: u16,
+    /// Dispute state of a deposit, none for withdrawals
+    dispute: Option<DisputeState>,
+}
+
+/// Checks transactions against the ones seen before them
+#[derive(Debug, Default)]
+pub struct Validator {
+    txs: HashMap<u32, SeenTx>,
+}
+
+impl Validator {
+    /// Check a transaction, returning the problem with it if any
+    pub fn check(&mut self, line: u64, tx: &TxType) -> Option<String> {
+        match tx {
+            TxType::Deposit(change) | TxType::Withdrawal(change) => {
+                if let Some(seen) = self.txs.get(&change.tx) {
+                    return Some(format!(
+                        "Duplicate tx {}, first used on line {}",
+                        change.tx, seen.line
+                    ));
+                }
+
+                let dispute = matches!(tx, TxType::Deposit(_)).then_some(DisputeState::None);
+                self.txs.insert(
+                    change.tx,
+                    SeenTx {
+                        line,
+                        client: change.client,
+                        dispute,
+                    },
+                );
+                None
+            }
+            TxType::Dispute(dispute) => {
+                let state = match self.deposit(tx.name(), dispute) {
+                    Ok(state) => state,
+                    Err(problem) => return Some(problem),
+                };
+                match state {
+                    DisputeState::None => {
+                        *state = DisputeState::Disputed;
+                        None
+                    }
+                    DisputeState::Disputed => Some(format!(
+                        "Dispute of tx {} that is already disputed",
+                        dispute.tx
+                    )),
+                    DisputeState::Chargeback => Some(format!(
+                        "Dispute of tx {} that is already charged back",
+                        dispute.tx
+                    )),
+                }
+            }
+            TxType::Resolve(dispute) | TxType::Chargeback(dispute) => {
+                let name = tx.name();
+                let state = match self.deposit(name, dispute) {
+                    Ok(state) => state,
+                    Err(problem) => return Some(problem),
+                };
+                if *state != DisputeState::Disputed {
+                    return Some(format!(
+                        "{} of tx {} without a dispute",
+                        capitalize(name),
+                        dispute.tx
+                    ));
+                }
+
+                *state = match tx {
+                    TxType::Resolve(_) => DisputeState::None,
+                    _ => DisputeState::Chargeback,
+                };
+                None
+            }
+        }
+    }
+
+    /// Dispute state of the deposit a dispute refers to
+    fn deposit(&mut self, name: &str, dispute: &Dispute) -> Result<&mut DisputeState, String> {
+        let name = capitalize(name);
+        let Some(seen) = self.txs.get_mut(&dispute.tx) else {
+            return Err(format!("{name} of unknown tx {}", dispute.tx));
+        };
+        if seen.client != dispute.client {
+            return Err(format!(
+                "{name} by client {} of tx {} of client {}",
+                dispute.client, dispute.tx, seen.client
+            ));
+        }
+        seen.dispute
+            .as_mut()
+            .ok_or_else(|| format!("{name} of withdrawal tx {}", dispute.tx))
+    }
+}
+
+/// Validate every row of the input, returning all problems in the order of the input
+pub fn validate<R: Read>(
+    reader: &mut Reader<R>,
+    format: &AmountFormat,
+) -> anyhow::Result<Vec<Problem>> {
+    let mut validator = Validator::default();
+    let mut problems = Vec::new();
+    for (line, tx) in numbered_record_iter(reader, format)? {
+        let message = match tx {
+            Ok(tx) => validator.check(line, &tx),
+            Err(err) => Some(format!("{err:#}")),
+        };
+        if let Some(message) = message {
+            problems.push(Problem { line, message });
+        }
+    }
+    Ok(problems)
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}